@@ -0,0 +1,52 @@
+-- Tables queried by src/db.rs, as they existed before versioned migrations.
+-- IF NOT EXISTS so databases created by hand can adopt the migrations as-is.
+CREATE TABLE IF NOT EXISTS source_type
+(
+    id          INTEGER PRIMARY KEY,
+    name        TEXT NOT NULL UNIQUE,
+    description TEXT
+);
+
+-- Source types referenced by id in the code (see models::WEBSITE_SOURCE_TYPE_ID)
+INSERT INTO source_type (id, name, description)
+VALUES (5, 'Website', 'A website, discovered from its URL or an OPML outline')
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS source
+(
+    id               UUID PRIMARY KEY,
+    name             TEXT        NOT NULL,
+    url              TEXT        NOT NULL UNIQUE,
+    type_id          INTEGER     NOT NULL REFERENCES source_type (id),
+    paywall          BOOLEAN,
+    feed_available   BOOLEAN,
+    description      TEXT,
+    short_name       TEXT,
+    state            TEXT,
+    city             TEXT,
+    create_timestamp TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS feed
+(
+    id               UUID PRIMARY KEY,
+    source_id        UUID        NOT NULL REFERENCES source (id),
+    url              TEXT        NOT NULL UNIQUE,
+    title            TEXT,
+    create_timestamp TIMESTAMPTZ NOT NULL DEFAULT now(),
+    feed_type        TEXT,
+    ttl              INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS news
+(
+    id                  UUID PRIMARY KEY,
+    feed_id             UUID        NOT NULL REFERENCES feed (id),
+    guid                TEXT        NOT NULL UNIQUE,
+    title               TEXT        NOT NULL,
+    published_timestamp TIMESTAMPTZ NOT NULL,
+    url                 TEXT        NOT NULL,
+    create_timestamp    TIMESTAMPTZ NOT NULL DEFAULT now(),
+    raw_content_path    TEXT,
+    text_content_path   TEXT
+);
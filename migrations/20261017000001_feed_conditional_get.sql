@@ -0,0 +1,4 @@
+-- HTTP cache validators from the last successful feed fetch, sent back as
+-- If-None-Match / If-Modified-Since on the next poll
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS etag TEXT;
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS last_modified TEXT;
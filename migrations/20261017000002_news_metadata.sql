@@ -0,0 +1,19 @@
+-- Item metadata carried by the feed itself, so items can be searched and
+-- filtered without downloading the linked pages
+ALTER TABLE news ADD COLUMN IF NOT EXISTS authors TEXT;
+ALTER TABLE news ADD COLUMN IF NOT EXISTS summary TEXT;
+ALTER TABLE news ADD COLUMN IF NOT EXISTS content TEXT;
+ALTER TABLE news ADD COLUMN IF NOT EXISTS updated_timestamp TIMESTAMPTZ;
+
+CREATE TABLE IF NOT EXISTS category
+(
+    id   SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS news_category
+(
+    news_id     UUID    NOT NULL REFERENCES news (id) ON DELETE CASCADE,
+    category_id INTEGER NOT NULL REFERENCES category (id) ON DELETE CASCADE,
+    PRIMARY KEY (news_id, category_id)
+);
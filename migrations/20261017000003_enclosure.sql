@@ -0,0 +1,17 @@
+-- Podcast / video attachments of a news item: RSS <enclosure>, Atom rel="enclosure"
+-- links, JSON Feed attachments and Media RSS media:content / media:thumbnail
+CREATE TABLE IF NOT EXISTS enclosure
+(
+    id               UUID PRIMARY KEY,
+    news_id          UUID        NOT NULL REFERENCES news (id) ON DELETE CASCADE,
+    url              TEXT        NOT NULL,
+    mime_type        TEXT,
+    length           BIGINT,
+    -- seconds
+    duration         INTEGER,
+    kind             TEXT        NOT NULL DEFAULT 'enclosure',
+    -- set when the enclosure was downloaded (DOWNLOAD_ENCLOSURES=true)
+    content_path     TEXT,
+    create_timestamp TIMESTAMPTZ NOT NULL DEFAULT now(),
+    UNIQUE (news_id, url)
+);
@@ -0,0 +1,20 @@
+-- Detect edits to published items: a hash of the inline content (or summary)
+-- is compared on every poll, and the previous values are kept in news_revision
+ALTER TABLE news ADD COLUMN IF NOT EXISTS content_hash TEXT;
+
+CREATE TABLE IF NOT EXISTS news_revision
+(
+    id                 BIGSERIAL PRIMARY KEY,
+    news_id            UUID        NOT NULL REFERENCES news (id) ON DELETE CASCADE,
+    title              TEXT        NOT NULL,
+    url                TEXT        NOT NULL,
+    summary            TEXT,
+    content            TEXT,
+    content_hash       TEXT,
+    -- when the previous values were first seen (news.updated_timestamp, or news.create_timestamp)
+    valid_from         TIMESTAMPTZ NOT NULL,
+    -- when they were replaced
+    revision_timestamp TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS news_revision_news_id_idx ON news_revision (news_id);
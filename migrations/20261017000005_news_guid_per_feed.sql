@@ -0,0 +1,24 @@
+-- guids are only unique within a feed: two feeds using short guids like "1" must
+-- not share a news row. The old global constraint guaranteed (feed_id, guid) is
+-- already unique, so existing rows need no rewriting.
+DO
+$$
+    DECLARE
+        c record;
+    BEGIN
+        FOR c IN SELECT con.conname
+                 FROM pg_constraint con
+                          JOIN pg_attribute a ON a.attrelid = con.conrelid AND a.attnum = con.conkey[1]
+                 WHERE con.conrelid = 'news'::regclass
+                   AND con.contype = 'u'
+                   AND array_length(con.conkey, 1) = 1
+                   AND a.attname = 'guid'
+            LOOP
+                EXECUTE format('ALTER TABLE news DROP CONSTRAINT %I', c.conname);
+            END LOOP;
+
+        IF NOT EXISTS (SELECT 1 FROM pg_constraint WHERE conname = 'news_feed_id_guid_key') THEN
+            ALTER TABLE news ADD CONSTRAINT news_feed_id_guid_key UNIQUE (feed_id, guid);
+        END IF;
+    END
+$$;
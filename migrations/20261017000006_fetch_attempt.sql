@@ -0,0 +1,22 @@
+-- One row per poll of a feed or page, successful or not, for spotting failing and stale feeds
+CREATE TABLE IF NOT EXISTS fetch_attempt
+(
+    id                 UUID PRIMARY KEY,
+    -- null when the feed isn't stored yet (its first fetch failed) or the url is a web page
+    feed_id            UUID REFERENCES feed (id) ON DELETE CASCADE,
+    url                TEXT        NOT NULL,
+    started_timestamp  TIMESTAMPTZ NOT NULL,
+    finished_timestamp TIMESTAMPTZ NOT NULL,
+    http_status        INTEGER,
+    bytes              BIGINT,
+    -- SHA-256 of the response body
+    content_hash       TEXT,
+    -- sniffed format: rss, atom, rdf, json_feed, html or unknown
+    format             TEXT,
+    new_items          INTEGER,
+    -- null on success; otherwise network, http, not_a_feed, parse, storage or io
+    error_class        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS fetch_attempt_feed_id_idx ON fetch_attempt (feed_id, started_timestamp);
+CREATE INDEX IF NOT EXISTS fetch_attempt_started_timestamp_idx ON fetch_attempt (started_timestamp);
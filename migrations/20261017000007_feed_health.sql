@@ -0,0 +1,18 @@
+-- Feed health: failures back off the next poll exponentially, and feeds that keep failing
+-- or stop publishing are marked dead and no longer polled by the daemon
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS status TEXT NOT NULL DEFAULT 'active';
+-- why and when the status last changed
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS status_reason TEXT;
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS status_timestamp TIMESTAMPTZ;
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS consecutive_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS last_success_timestamp TIMESTAMPTZ;
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS last_new_item_timestamp TIMESTAMPTZ;
+-- set while backing off after failures
+ALTER TABLE feed ADD COLUMN IF NOT EXISTS next_poll_timestamp TIMESTAMPTZ;
+
+UPDATE feed
+SET last_new_item_timestamp = n.latest
+FROM (SELECT feed_id, max(create_timestamp) AS latest FROM news GROUP BY feed_id) n
+WHERE feed.id = n.feed_id AND feed.last_new_item_timestamp IS NULL;
+
+CREATE INDEX IF NOT EXISTS feed_status_idx ON feed (status);
@@ -0,0 +1,10 @@
+-- Former urls of feeds that moved permanently (301/308), so lookups and imports
+-- of an old url find the same feed instead of creating a second one
+CREATE TABLE IF NOT EXISTS feed_url_alias
+(
+    url              TEXT PRIMARY KEY,
+    feed_id          UUID        NOT NULL REFERENCES feed (id) ON DELETE CASCADE,
+    create_timestamp TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS feed_url_alias_feed_id_idx ON feed_url_alias (feed_id);
@@ -0,0 +1,2 @@
+-- TLS certificates are verified unless a source explicitly opts out
+ALTER TABLE source ADD COLUMN IF NOT EXISTS skip_tls_verify BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- Per-source overrides of the global per-host politeness limits (HOST_REQUESTS_PER_SECOND, HOST_MAX_IN_FLIGHT)
+ALTER TABLE source ADD COLUMN IF NOT EXISTS requests_per_second DOUBLE PRECISION;
+ALTER TABLE source ADD COLUMN IF NOT EXISTS max_in_flight INTEGER;
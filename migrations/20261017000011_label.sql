@@ -0,0 +1,23 @@
+-- Labels (e.g. the folders of an imported OPML file) attached to sources and feeds
+CREATE TABLE IF NOT EXISTS label
+(
+    id   SERIAL PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS source_label
+(
+    source_id UUID    NOT NULL REFERENCES source (id) ON DELETE CASCADE,
+    label_id  INTEGER NOT NULL REFERENCES label (id) ON DELETE CASCADE,
+    PRIMARY KEY (source_id, label_id)
+);
+
+CREATE TABLE IF NOT EXISTS feed_label
+(
+    feed_id  UUID    NOT NULL REFERENCES feed (id) ON DELETE CASCADE,
+    label_id INTEGER NOT NULL REFERENCES label (id) ON DELETE CASCADE,
+    PRIMARY KEY (feed_id, label_id)
+);
+
+CREATE INDEX IF NOT EXISTS source_label_label_id_idx ON source_label (label_id);
+CREATE INDEX IF NOT EXISTS feed_label_label_id_idx ON feed_label (label_id);
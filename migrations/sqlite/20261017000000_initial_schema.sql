@@ -0,0 +1,101 @@
+-- SQLite schema, matching the Postgres migrations in the parent directory.
+-- UUIDs are stored as 16-byte BLOBs and timestamps as RFC 3339 TEXT.
+CREATE TABLE IF NOT EXISTS source_type
+(
+    id          INTEGER PRIMARY KEY,
+    name        TEXT NOT NULL UNIQUE,
+    description TEXT
+);
+
+INSERT INTO source_type (id, name, description)
+VALUES (5, 'Website', 'A website, discovered from its URL or an OPML outline')
+ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS source
+(
+    id               BLOB PRIMARY KEY,
+    name             TEXT    NOT NULL,
+    url              TEXT    NOT NULL UNIQUE,
+    type_id          INTEGER NOT NULL REFERENCES source_type (id),
+    paywall          BOOLEAN,
+    feed_available   BOOLEAN,
+    description      TEXT,
+    short_name       TEXT,
+    state            TEXT,
+    city             TEXT,
+    create_timestamp TEXT    NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS feed
+(
+    id               BLOB PRIMARY KEY,
+    source_id        BLOB NOT NULL REFERENCES source (id),
+    url              TEXT NOT NULL UNIQUE,
+    title            TEXT,
+    create_timestamp TEXT NOT NULL,
+    feed_type        TEXT,
+    ttl              INTEGER,
+    etag             TEXT,
+    last_modified    TEXT
+);
+
+CREATE TABLE IF NOT EXISTS news
+(
+    id                  BLOB PRIMARY KEY,
+    feed_id             BLOB NOT NULL REFERENCES feed (id),
+    guid                TEXT NOT NULL,
+    title               TEXT NOT NULL,
+    published_timestamp TEXT NOT NULL,
+    url                 TEXT NOT NULL,
+    create_timestamp    TEXT NOT NULL,
+    raw_content_path    TEXT,
+    text_content_path   TEXT,
+    authors             TEXT,
+    summary             TEXT,
+    content             TEXT,
+    updated_timestamp   TEXT,
+    content_hash        TEXT,
+    UNIQUE (feed_id, guid)
+);
+
+CREATE TABLE IF NOT EXISTS news_revision
+(
+    id                 INTEGER PRIMARY KEY AUTOINCREMENT,
+    news_id            BLOB NOT NULL REFERENCES news (id) ON DELETE CASCADE,
+    title              TEXT NOT NULL,
+    url                TEXT NOT NULL,
+    summary            TEXT,
+    content            TEXT,
+    content_hash       TEXT,
+    valid_from         TEXT NOT NULL,
+    revision_timestamp TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS news_revision_news_id_idx ON news_revision (news_id);
+
+CREATE TABLE IF NOT EXISTS category
+(
+    id   INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS news_category
+(
+    news_id     BLOB    NOT NULL REFERENCES news (id) ON DELETE CASCADE,
+    category_id INTEGER NOT NULL REFERENCES category (id) ON DELETE CASCADE,
+    PRIMARY KEY (news_id, category_id)
+);
+
+CREATE TABLE IF NOT EXISTS enclosure
+(
+    id               BLOB PRIMARY KEY,
+    news_id          BLOB NOT NULL REFERENCES news (id) ON DELETE CASCADE,
+    url              TEXT NOT NULL,
+    mime_type        TEXT,
+    length           INTEGER,
+    duration         INTEGER,
+    kind             TEXT NOT NULL DEFAULT 'enclosure',
+    content_path     TEXT,
+    create_timestamp TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (news_id, url)
+);
@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS fetch_attempt
+(
+    id                 BLOB PRIMARY KEY,
+    feed_id            BLOB REFERENCES feed (id) ON DELETE CASCADE,
+    url                TEXT NOT NULL,
+    started_timestamp  TEXT NOT NULL,
+    finished_timestamp TEXT NOT NULL,
+    http_status        INTEGER,
+    bytes              INTEGER,
+    content_hash       TEXT,
+    format             TEXT,
+    new_items          INTEGER,
+    error_class        TEXT
+);
+
+CREATE INDEX IF NOT EXISTS fetch_attempt_feed_id_idx ON fetch_attempt (feed_id, started_timestamp);
+CREATE INDEX IF NOT EXISTS fetch_attempt_started_timestamp_idx ON fetch_attempt (started_timestamp);
@@ -0,0 +1,13 @@
+ALTER TABLE feed ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+ALTER TABLE feed ADD COLUMN status_reason TEXT;
+ALTER TABLE feed ADD COLUMN status_timestamp TEXT;
+ALTER TABLE feed ADD COLUMN consecutive_failures INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE feed ADD COLUMN last_success_timestamp TEXT;
+ALTER TABLE feed ADD COLUMN last_new_item_timestamp TEXT;
+ALTER TABLE feed ADD COLUMN next_poll_timestamp TEXT;
+
+UPDATE feed
+SET last_new_item_timestamp = (SELECT max(create_timestamp) FROM news WHERE news.feed_id = feed.id)
+WHERE last_new_item_timestamp IS NULL;
+
+CREATE INDEX IF NOT EXISTS feed_status_idx ON feed (status);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS feed_url_alias
+(
+    url              TEXT PRIMARY KEY,
+    feed_id          BLOB NOT NULL REFERENCES feed (id) ON DELETE CASCADE,
+    create_timestamp TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS feed_url_alias_feed_id_idx ON feed_url_alias (feed_id);
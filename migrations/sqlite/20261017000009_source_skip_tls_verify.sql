@@ -0,0 +1 @@
+ALTER TABLE source ADD COLUMN skip_tls_verify BOOLEAN NOT NULL DEFAULT 0;
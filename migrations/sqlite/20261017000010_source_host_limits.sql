@@ -0,0 +1,2 @@
+ALTER TABLE source ADD COLUMN requests_per_second REAL;
+ALTER TABLE source ADD COLUMN max_in_flight INTEGER;
@@ -0,0 +1,22 @@
+CREATE TABLE IF NOT EXISTS label
+(
+    id   INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
+
+CREATE TABLE IF NOT EXISTS source_label
+(
+    source_id BLOB    NOT NULL REFERENCES source (id) ON DELETE CASCADE,
+    label_id  INTEGER NOT NULL REFERENCES label (id) ON DELETE CASCADE,
+    PRIMARY KEY (source_id, label_id)
+);
+
+CREATE TABLE IF NOT EXISTS feed_label
+(
+    feed_id  BLOB    NOT NULL REFERENCES feed (id) ON DELETE CASCADE,
+    label_id INTEGER NOT NULL REFERENCES label (id) ON DELETE CASCADE,
+    PRIMARY KEY (feed_id, label_id)
+);
+
+CREATE INDEX IF NOT EXISTS source_label_label_id_idx ON source_label (label_id);
+CREATE INDEX IF NOT EXISTS feed_label_label_id_idx ON feed_label (label_id);
@@ -0,0 +1,202 @@
+//! Settings read from the environment
+
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Used when `DATABASE_URL` isn't set and the binary was built without the `postgres` feature
+const DEFAULT_SQLITE_URL: &str = "sqlite:feed-fetcher.db";
+
+#[derive(Debug, Clone)]
+pub(crate) struct DatabaseConfig {
+    /// `DATABASE_URL`, `postgres://...` or `sqlite:...`
+    pub url: String,
+    /// `DATABASE_MAX_CONNECTIONS`, default 10
+    pub max_connections: u32,
+    /// `DATABASE_ACQUIRE_TIMEOUT` in seconds, default 5
+    pub acquire_timeout: Duration,
+    /// `DATABASE_IDLE_TIMEOUT` in seconds, default 600
+    pub idle_timeout: Duration,
+}
+
+impl DatabaseConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let url = match env::var("DATABASE_URL") {
+            Ok(url) => url,
+            // SQLite-only builds work out of the box
+            Err(_) if cfg!(not(feature = "postgres")) => DEFAULT_SQLITE_URL.to_string(),
+            Err(_) => anyhow::bail!("DATABASE_URL must be set"),
+        };
+        Ok(Self {
+            url,
+            max_connections: env_or("DATABASE_MAX_CONNECTIONS", 10)?,
+            acquire_timeout: Duration::from_secs(env_or("DATABASE_ACQUIRE_TIMEOUT", 5)?),
+            idle_timeout: Duration::from_secs(env_or("DATABASE_IDLE_TIMEOUT", 600)?),
+        })
+    }
+}
+
+/// User-Agent sent with every request, so publishers can tell who is polling them
+const DEFAULT_USER_AGENT: &str = concat!("feed-fetcher/", env!("CARGO_PKG_VERSION"), " (+https://github.com/thesurlydev/feed-fetcher)");
+
+/// Settings of the HTTP client shared by all fetches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HttpConfig {
+    /// `FETCH_CONNECT_TIMEOUT` in seconds, default 10
+    pub connect_timeout: Duration,
+    /// `FETCH_TIMEOUT` in seconds for a whole feed or page request, default 30
+    pub timeout: Duration,
+    /// `FETCH_DOWNLOAD_TIMEOUT` in seconds for a whole article or enclosure download, default 600
+    pub download_timeout: Duration,
+    /// `FETCH_MAX_BODY_BYTES` of a feed or page, default 20 MiB
+    pub max_body_bytes: u64,
+    /// `FETCH_PROXY`, e.g. `http://proxy:3128`; the usual `HTTP_PROXY` / `HTTPS_PROXY` are honored too
+    pub proxy: Option<String>,
+    /// `FETCH_USER_AGENT`
+    pub user_agent: String,
+}
+
+impl HttpConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            connect_timeout: Duration::from_secs(env_or("FETCH_CONNECT_TIMEOUT", default.connect_timeout.as_secs())?),
+            timeout: Duration::from_secs(env_or("FETCH_TIMEOUT", default.timeout.as_secs())?),
+            download_timeout: Duration::from_secs(env_or("FETCH_DOWNLOAD_TIMEOUT", default.download_timeout.as_secs())?),
+            max_body_bytes: env_or("FETCH_MAX_BODY_BYTES", default.max_body_bytes)?,
+            proxy: env::var("FETCH_PROXY").ok().filter(|p| !p.is_empty()),
+            user_agent: env_or("FETCH_USER_AGENT", default.user_agent)?,
+        })
+    }
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            timeout: Duration::from_secs(30),
+            download_timeout: Duration::from_secs(600),
+            max_body_bytes: 20 * 1024 * 1024,
+            proxy: None,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+        }
+    }
+}
+
+/// Politeness limits per host, shared by feed fetches, article downloads and Playwright
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct HostLimits {
+    /// `HOST_REQUESTS_PER_SECOND`, how fast the token bucket refills; 0 disables it. Default 1
+    pub requests_per_second: f64,
+    /// `HOST_BURST` requests allowed back to back once the bucket is full, default 5
+    pub burst: u32,
+    /// `HOST_MAX_IN_FLIGHT` requests at once, default 2
+    pub max_in_flight: usize,
+}
+
+impl HostLimits {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            requests_per_second: env_or("HOST_REQUESTS_PER_SECOND", default.requests_per_second)?,
+            burst: env_or("HOST_BURST", default.burst)?,
+            max_in_flight: env_or("HOST_MAX_IN_FLIGHT", default.max_in_flight)?,
+        })
+    }
+}
+
+impl Default for HostLimits {
+    fn default() -> Self {
+        Self { requests_per_second: 1.0, burst: 5, max_in_flight: 2 }
+    }
+}
+
+/// How robots.txt is honored for article downloads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct RobotsConfig {
+    /// `ROBOTS_CACHE_TTL` in seconds a host's robots.txt is kept before it is fetched again, default 86400
+    pub cache_ttl: Duration,
+    /// Product token matched against `User-agent` lines: `FETCH_USER_AGENT` up to the first `/`
+    pub agent: String,
+}
+
+impl RobotsConfig {
+    pub fn from_env(http: &HttpConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            cache_ttl: Duration::from_secs(env_or("ROBOTS_CACHE_TTL", 86400)?),
+            agent: product_token(&http.user_agent),
+        })
+    }
+}
+
+impl Default for RobotsConfig {
+    fn default() -> Self {
+        Self { cache_ttl: Duration::from_secs(86400), agent: product_token(DEFAULT_USER_AGENT) }
+    }
+}
+
+/// `feed-fetcher` of `feed-fetcher/1.0 (+https://...)`
+fn product_token(user_agent: &str) -> String {
+    user_agent.split(['/', ' ']).next().unwrap_or_default().to_lowercase()
+}
+
+/// How `opml!` imports its feeds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ImportConfig {
+    /// `OPML_IMPORT_WORKERS` feeds fetched at once, default 8
+    pub workers: usize,
+}
+
+impl ImportConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self { workers: env_or("OPML_IMPORT_WORKERS", 8)? })
+    }
+}
+
+/// When feeds are given up on (marked dead)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct HealthConfig {
+    /// `FEED_DEAD_AFTER_FAILURES` consecutive failed polls, default 10
+    pub dead_after_failures: i32,
+    /// `FEED_DEAD_AFTER_DAYS` without new items, default 90
+    pub dead_after_days: i64,
+}
+
+impl HealthConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let default = Self::default();
+        Ok(Self {
+            dead_after_failures: env_or("FEED_DEAD_AFTER_FAILURES", default.dead_after_failures)?,
+            dead_after_days: env_or("FEED_DEAD_AFTER_DAYS", default.dead_after_days)?,
+        })
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self { dead_after_failures: 10, dead_after_days: 90 }
+    }
+}
+
+/// Parse an environment variable, or use the default when it isn't set
+pub(crate) fn env_or<T: FromStr>(name: &str, default: T) -> anyhow::Result<T> {
+    match env::var(name) {
+        Ok(v) => v.trim().parse().map_err(|_| anyhow::anyhow!("Invalid value for {}: '{}'", name, v)),
+        Err(_) => Ok(default),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_env_or() {
+        env::set_var("FEED_FETCHER_TEST_ENV_OR", " 42 ");
+        assert_eq!(env_or("FEED_FETCHER_TEST_ENV_OR", 7u32).unwrap(), 42);
+        env::set_var("FEED_FETCHER_TEST_ENV_OR", "lots");
+        assert!(env_or("FEED_FETCHER_TEST_ENV_OR", 7u32).is_err());
+        env::remove_var("FEED_FETCHER_TEST_ENV_OR");
+        assert_eq!(env_or("FEED_FETCHER_TEST_ENV_OR", 7u32).unwrap(), 7);
+    }
+}
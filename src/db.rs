@@ -1,112 +1,471 @@
-use std::env;
-use sqlx::{Pool, Postgres, query_as};
+//! Postgres storage; queries are checked at compile time against `DATABASE_URL`
+
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::info;
+use sqlx::{Pool, Postgres, query_as, Transaction};
+use sqlx::migrate::MigrateDatabase;
 use sqlx::postgres::PgPoolOptions;
-use crate::models::{Feed, NewsItem, Source, SourceType};
-
-#[allow(dead_code)]
-pub(crate) async fn source_types() -> Result<Vec<SourceType>, sqlx::Error> {
-    let pool: Pool<Postgres> = get_pool().await;
-    query_as!(SourceType, "select * from source_type")
-        .fetch_all(&pool)
-        .await
-}
+use crate::config::DatabaseConfig;
+use crate::models::{Enclosure, FetchAttempt, Feed, Label, NewsItem, SaveStatus, Source, SourceType};
+use crate::storage::{duplicate_guids, news_change, NewsChange, Storage};
 
-#[allow(dead_code)]
-pub(crate) async fn sources() -> Result<Vec<Source>, sqlx::Error> {
-    let pool: Pool<Postgres> = get_pool().await;
-    query_as!(Source, r#"SELECT * FROM source"#)
-        .fetch_all(&pool)
-        .await
+/// Postgres connection pool shared by the whole app; create it once and pass it around
+#[derive(Debug, Clone)]
+pub(crate) struct PgStorage {
+    pool: Pool<Postgres>,
 }
 
-#[allow(dead_code)]
-pub(crate) async fn source_type_by_name(name: &str) -> Result<SourceType, sqlx::Error> {
-    let pool: Pool<Postgres> = get_pool().await;
-    query_as!(SourceType, r#"SELECT * FROM source_type WHERE name = $1"#, name)
-        .fetch_one(&pool)
-        .await
-}
+impl PgStorage {
+    pub(crate) async fn connect(config: &DatabaseConfig) -> anyhow::Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .acquire_timeout(config.acquire_timeout)
+            .idle_timeout(config.idle_timeout)
+            .connect(&config.url)
+            .await?;
+        Ok(Self { pool })
+    }
 
-#[allow(dead_code)]
-pub(crate) async fn feeds() -> Result<Vec<Feed>, sqlx::Error> {
-    let pool: Pool<Postgres> = get_pool().await;
-    query_as!(Feed, r#"SELECT * FROM feed"#)
-        .fetch_all(&pool)
-        .await
-}
+    /// Create the database if needed, connect, then apply the migrations embedded from `migrations/`
+    pub(crate) async fn migrate(config: &DatabaseConfig) -> anyhow::Result<Self> {
+        if !Postgres::database_exists(&config.url).await? {
+            info!("Creating database");
+            Postgres::create_database(&config.url).await?;
+        }
+        let db = Self::connect(config).await?;
+        sqlx::migrate!().run(&db.pool).await?;
+        Ok(db)
+    }
 
-#[allow(dead_code)]
-pub(crate) async fn news() -> Result<Vec<NewsItem>, sqlx::Error> {
-    let pool: Pool<Postgres> = get_pool().await;
-    query_as!(NewsItem, r#"SELECT * FROM news"#)
-        .fetch_all(&pool)
-        .await
-}
+    async fn save_labels(tx: &mut Transaction<'_, Postgres>, labels: &[String]) -> anyhow::Result<()> {
+        sqlx::query!("INSERT INTO label (name) SELECT DISTINCT name FROM UNNEST($1::text[]) AS t(name) ON CONFLICT (name) DO NOTHING", labels)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
 
-#[allow(dead_code)]
-pub(crate) async fn save_source_type(source_type: &SourceType) -> anyhow::Result<i32> {
-    let pool: Pool<Postgres> = get_pool().await;
-    let rec = sqlx::query!("INSERT INTO source_type (id, name) VALUES ($1, $2) RETURNING id", source_type.id, source_type.name)
-        .fetch_one(&pool)
-        .await?;
-    Ok(rec.id)
-}
+    /// Upsert the items of one feed with a handful of multi-row statements: one insert for new items,
+    /// one update for changed items (after copying their previous values to `news_revision`) and
+    /// two statements for categories. Returns each item's id and status, in order.
+    async fn upsert_feed(tx: &mut Transaction<'_, Postgres>, feed: &Feed) -> anyhow::Result<uuid::Uuid> {
+        let rec = sqlx::query!(r#"
+INSERT INTO feed (id, url, title, source_id, feed_type, ttl, etag, last_modified)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+ON CONFLICT (url) DO UPDATE SET title = COALESCE(EXCLUDED.title, feed.title), feed_type = EXCLUDED.feed_type,
+    ttl = EXCLUDED.ttl, etag = EXCLUDED.etag, last_modified = EXCLUDED.last_modified
+RETURNING id
+    "#,
+            feed.id, feed.url, feed.title, feed.source_id, feed.feed_type, feed.ttl, feed.etag, feed.last_modified)
+            .fetch_one(&mut *tx)
+            .await?;
+        Ok(rec.id)
+    }
+
+    async fn save_items(tx: &mut Transaction<'_, Postgres>, feed_id: uuid::Uuid, items: &[(NewsItem, Vec<String>)]) -> anyhow::Result<Vec<(uuid::Uuid, SaveStatus)>> {
+        let guids: Vec<String> = items.iter().map(|(ni, _)| ni.guid.clone()).collect();
+        let existing = sqlx::query!(r#"
+SELECT id, guid, title, url, content_hash
+FROM news
+WHERE feed_id = $1 AND guid = ANY($2)
+FOR UPDATE
+        "#,
+            feed_id, &guids)
+            .fetch_all(&mut *tx)
+            .await?;
+        let existing: HashMap<&str, _> = existing.iter().map(|row| (row.guid.as_str(), row)).collect();
+
+        let mut saved: Vec<(uuid::Uuid, SaveStatus)> = Vec::with_capacity(items.len());
+        let mut inserts = Vec::new();
+        let mut backfills = Vec::new();
+        let mut revisions = Vec::new();
+        for ((ni, _), duplicate_of) in items.iter().zip(duplicate_guids(items)) {
+            if let Some(j) = duplicate_of {
+                saved.push((saved[j].0, SaveStatus::Unchanged));
+                continue;
+            }
+            let Some(row) = existing.get(ni.guid.as_str()) else {
+                inserts.push(ni);
+                saved.push((ni.id, SaveStatus::Inserted));
+                continue;
+            };
+            match news_change(&row.title, &row.url, row.content_hash.as_deref(), ni) {
+                NewsChange::Unchanged => saved.push((row.id, SaveStatus::Unchanged)),
+                NewsChange::BackfillHash => {
+                    backfills.push((row.id, ni));
+                    saved.push((row.id, SaveStatus::Unchanged));
+                }
+                NewsChange::Revise => {
+                    revisions.push((row.id, ni));
+                    saved.push((row.id, SaveStatus::Updated));
+                }
+            }
+        }
+
+        if !inserts.is_empty() {
+            let inserted = sqlx::query!(r#"
+INSERT INTO news (id, title, url, published_timestamp, guid, feed_id, authors, summary, content, content_hash, updated_timestamp)
+SELECT id, title, url, published_timestamp, guid, $6, authors, summary, content, content_hash, updated_timestamp
+FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::timestamptz[], $5::text[], $7::text[], $8::text[], $9::text[], $10::text[], $11::timestamptz[])
+    AS t(id, title, url, published_timestamp, guid, authors, summary, content, content_hash, updated_timestamp)
+ON CONFLICT (feed_id, guid) DO NOTHING
+RETURNING guid
+            "#,
+                &inserts.iter().map(|ni| ni.id).collect::<Vec<_>>(),
+                &inserts.iter().map(|ni| ni.title.clone()).collect::<Vec<_>>(),
+                &inserts.iter().map(|ni| ni.url.clone()).collect::<Vec<_>>(),
+                &inserts.iter().map(|ni| ni.published_timestamp).collect::<Vec<_>>(),
+                &inserts.iter().map(|ni| ni.guid.clone()).collect::<Vec<_>>(),
+                feed_id,
+                &inserts.iter().map(|ni| ni.authors.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &inserts.iter().map(|ni| ni.summary.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &inserts.iter().map(|ni| ni.content.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &inserts.iter().map(|ni| ni.content_hash.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &inserts.iter().map(|ni| ni.updated_timestamp).collect::<Vec<_>>() as &[Option<DateTime<Utc>>])
+                .fetch_all(&mut *tx)
+                .await?;
+
+            // another writer inserted some of these guids first; report its rows as unchanged
+            let inserted: HashSet<String> = inserted.into_iter().map(|rec| rec.guid).collect();
+            let raced: Vec<String> = inserts.iter().filter(|ni| !inserted.contains(&ni.guid)).map(|ni| ni.guid.clone()).collect();
+            if !raced.is_empty() {
+                let rows = sqlx::query!("SELECT id, guid FROM news WHERE feed_id = $1 AND guid = ANY($2)", feed_id, &raced)
+                    .fetch_all(&mut *tx)
+                    .await?;
+                for row in rows {
+                    for ((ni, _), entry) in items.iter().zip(saved.iter_mut()) {
+                        if ni.guid == row.guid {
+                            *entry = (row.id, SaveStatus::Unchanged);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !backfills.is_empty() {
+            sqlx::query!(r#"
+UPDATE news
+SET summary = t.summary, content = t.content, content_hash = t.content_hash
+FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[]) AS t(id, summary, content, content_hash)
+WHERE news.id = t.id
+            "#,
+                &backfills.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+                &backfills.iter().map(|(_, ni)| ni.summary.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &backfills.iter().map(|(_, ni)| ni.content.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &backfills.iter().map(|(_, ni)| ni.content_hash.clone()).collect::<Vec<_>>() as &[Option<String>])
+                .execute(&mut *tx)
+                .await?;
+        }
 
-pub(crate) async fn get_pool() -> Pool<Postgres> {
-    let db_url = &env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgPoolOptions::new()
-        .max_connections(200)
-        .acquire_timeout(std::time::Duration::from_secs(5))
-        .idle_timeout(std::time::Duration::from_secs(3))
-        .connect(db_url.as_str())
-        .await
-        .expect("Failed to connect to Postgres")
+        if !revisions.is_empty() {
+            let ids: Vec<uuid::Uuid> = revisions.iter().map(|(id, _)| *id).collect();
+            sqlx::query!(r#"
+INSERT INTO news_revision (news_id, title, url, summary, content, content_hash, valid_from)
+SELECT id, title, url, summary, content, content_hash, COALESCE(updated_timestamp, create_timestamp)
+FROM news
+WHERE id = ANY($1)
+            "#,
+                &ids)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query!(r#"
+UPDATE news
+SET title = t.title, url = t.url, authors = t.authors, summary = t.summary, content = t.content,
+    content_hash = t.content_hash, updated_timestamp = COALESCE(t.updated_timestamp, now())
+FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[], $5::text[], $6::text[], $7::text[], $8::timestamptz[])
+    AS t(id, title, url, authors, summary, content, content_hash, updated_timestamp)
+WHERE news.id = t.id
+            "#,
+                &ids,
+                &revisions.iter().map(|(_, ni)| ni.title.clone()).collect::<Vec<_>>(),
+                &revisions.iter().map(|(_, ni)| ni.url.clone()).collect::<Vec<_>>(),
+                &revisions.iter().map(|(_, ni)| ni.authors.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &revisions.iter().map(|(_, ni)| ni.summary.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &revisions.iter().map(|(_, ni)| ni.content.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &revisions.iter().map(|(_, ni)| ni.content_hash.clone()).collect::<Vec<_>>() as &[Option<String>],
+                &revisions.iter().map(|(_, ni)| ni.updated_timestamp).collect::<Vec<_>>() as &[Option<DateTime<Utc>>])
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        // one (news id, category name) pair per category of each item
+        let (news_ids, names): (Vec<uuid::Uuid>, Vec<String>) = items.iter().zip(&saved)
+            .flat_map(|((_, categories), (id, _))| categories.iter().map(move |c| (*id, c.clone())))
+            .unzip();
+        if !names.is_empty() {
+            sqlx::query!(r#"
+INSERT INTO category (name)
+SELECT DISTINCT name FROM UNNEST($1::text[]) AS t(name)
+ON CONFLICT (name) DO NOTHING
+            "#,
+                &names)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query!(r#"
+INSERT INTO news_category (news_id, category_id)
+SELECT t.news_id, c.id
+FROM UNNEST($1::uuid[], $2::text[]) AS t(news_id, name)
+JOIN category c ON c.name = t.name
+ON CONFLICT DO NOTHING
+            "#,
+                &news_ids, &names)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        Ok(saved)
+    }
 }
 
-pub(crate) async fn save_source(source: &Source, pool: &Pool<Postgres>) -> anyhow::Result<uuid::Uuid> {
-    let rec = sqlx::query!(r#"
+#[async_trait]
+impl Storage for PgStorage {
+    async fn source_types(&self) -> anyhow::Result<Vec<SourceType>> {
+        Ok(query_as!(SourceType, "select * from source_type")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn sources(&self) -> anyhow::Result<Vec<Source>> {
+        Ok(query_as!(Source, r#"SELECT * FROM source"#)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn source_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Source>> {
+        Ok(query_as!(Source, r#"SELECT * FROM source WHERE id = $1"#, id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn source_by_url(&self, url: &str) -> anyhow::Result<Option<Source>> {
+        Ok(query_as!(Source, r#"SELECT * FROM source WHERE url = $1"#, url)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn source_type_by_name(&self, name: &str) -> anyhow::Result<Option<SourceType>> {
+        Ok(query_as!(SourceType, r#"SELECT * FROM source_type WHERE name = $1"#, name)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn feeds(&self) -> anyhow::Result<Vec<Feed>> {
+        Ok(query_as!(Feed, r#"SELECT * FROM feed"#)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn feed_by_url(&self, url: &str) -> anyhow::Result<Option<Feed>> {
+        Ok(query_as!(Feed, r#"
+SELECT * FROM feed
+WHERE url = $1 OR id IN (SELECT feed_id FROM feed_url_alias WHERE url = $1)
+ORDER BY url = $1 DESC
+LIMIT 1
+        "#, url)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn feed_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Feed>> {
+        Ok(query_as!(Feed, r#"SELECT * FROM feed WHERE id = $1"#, id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn news(&self) -> anyhow::Result<Vec<NewsItem>> {
+        Ok(query_as!(NewsItem, r#"SELECT * FROM news"#)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn save_source_type(&self, source_type: &SourceType) -> anyhow::Result<i32> {
+        let rec = sqlx::query!("INSERT INTO source_type (id, name) VALUES ($1, $2) RETURNING id", source_type.id, source_type.name)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(rec.id)
+    }
+
+    async fn save_source(&self, source: &Source) -> anyhow::Result<uuid::Uuid> {
+        let rec = sqlx::query!(r#"
 WITH e AS(
-INSERT INTO source (id, name, url, type_id, paywall, feed_available, description, short_name, state, city, create_timestamp)
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+INSERT INTO source (id, name, url, type_id, paywall, feed_available, description, short_name, state, city, create_timestamp, skip_tls_verify,
+                    requests_per_second, max_in_flight)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
 ON CONFLICT (url) DO NOTHING
 RETURNING id
 )
 SELECT * FROM e UNION SELECT id FROM source WHERE url = $3
 "#,
-        source.id, source.name, source.url, source.type_id, source.paywall, source.feed_available, source.description, source.short_name, source.state, source.city, source.create_timestamp)
-        .fetch_one(pool)
-        .await?;
-    Ok(rec.id.unwrap())
-}
+            source.id, source.name, source.url, source.type_id, source.paywall, source.feed_available, source.description, source.short_name, source.state, source.city, source.create_timestamp, source.skip_tls_verify,
+            source.requests_per_second, source.max_in_flight)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(rec.id.unwrap())
+    }
 
-pub(crate) async fn save_feed(feed: &Feed, pool: &Pool<Postgres>) -> anyhow::Result<uuid::Uuid> {
-    let rec = sqlx::query!(r#"
-WITH e AS(
-INSERT INTO feed (id, url, title, source_id, feed_type)
-VALUES ($1, $2, $3, $4, $5)
-ON CONFLICT (url) DO NOTHING
-RETURNING id
-)
-SELECT * FROM e UNION SELECT id FROM feed WHERE url = $2
-    "#,
-        feed.id, feed.url, feed.title, feed.source_id, feed.feed_type)
-        .fetch_one(pool)
-        .await?;
-    Ok(rec.id.unwrap())
-}
+    async fn save_feed(&self, feed: &Feed) -> anyhow::Result<uuid::Uuid> {
+        let mut tx = self.pool.begin().await?;
+        let id = Self::upsert_feed(&mut tx, feed).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
 
-pub(crate) async fn save_news_item(ni: &NewsItem, pool: &Pool<Postgres>) -> anyhow::Result<uuid::Uuid> {
-    let rec = sqlx::query!(r#"
-WITH e AS(
-INSERT INTO news (id, title, url, published_timestamp, guid, feed_id)
-VALUES ($1, $2, $3, $4, $5, $6)
-ON CONFLICT (guid) DO NOTHING
-RETURNING id
-)
-SELECT * FROM e UNION SELECT id FROM news WHERE guid = $5
+    async fn move_feed(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query!(r#"
+INSERT INTO feed_url_alias (url, feed_id)
+SELECT url, id FROM feed WHERE id = $1
+ON CONFLICT (url) DO UPDATE SET feed_id = EXCLUDED.feed_id
+        "#,
+            id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query!("UPDATE feed SET url = $2 WHERE id = $1", id, url)
+            .execute(&mut tx)
+            .await?;
+        // moving back to a former url
+        sqlx::query!("DELETE FROM feed_url_alias WHERE url = $1", url)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn save_feed_alias(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()> {
+        sqlx::query!("INSERT INTO feed_url_alias (url, feed_id) VALUES ($1, $2) ON CONFLICT (url) DO UPDATE SET feed_id = EXCLUDED.feed_id", url, id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_feed_health(&self, feed: &Feed) -> anyhow::Result<()> {
+        sqlx::query!(r#"
+UPDATE feed
+SET status = $2, status_reason = $3, status_timestamp = $4, consecutive_failures = $5,
+    last_success_timestamp = $6, last_new_item_timestamp = $7, next_poll_timestamp = $8
+WHERE id = $1
+        "#,
+            feed.id, feed.status, feed.status_reason, feed.status_timestamp, feed.consecutive_failures,
+            feed.last_success_timestamp, feed.last_new_item_timestamp, feed.next_poll_timestamp)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn labels(&self) -> anyhow::Result<Vec<Label>> {
+        Ok(query_as!(Label, "SELECT * FROM label ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn save_source_labels(&self, source_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::save_labels(&mut tx, labels).await?;
+        sqlx::query!(r#"
+INSERT INTO source_label (source_id, label_id)
+SELECT $1, id FROM label WHERE name = ANY($2)
+ON CONFLICT DO NOTHING
+        "#,
+            source_id, labels)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn save_feed_labels(&self, feed_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        Self::save_labels(&mut tx, labels).await?;
+        sqlx::query!(r#"
+INSERT INTO feed_label (feed_id, label_id)
+SELECT $1, id FROM label WHERE name = ANY($2)
+ON CONFLICT DO NOTHING
         "#,
-        ni.id, ni.title, ni.url, ni.published_timestamp, ni.guid, ni.feed_id)
-        .fetch_one(pool)
-        .await?;
-    Ok(rec.id.unwrap())
-}
\ No newline at end of file
+            feed_id, labels)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn sources_by_label(&self, name: &str) -> anyhow::Result<Vec<Source>> {
+        Ok(query_as!(Source, r#"
+SELECT s.* FROM source s
+JOIN source_label sl ON sl.source_id = s.id
+JOIN label l ON l.id = sl.label_id
+WHERE l.name = $1
+ORDER BY s.url
+        "#, name)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn feeds_by_label(&self, name: &str) -> anyhow::Result<Vec<Feed>> {
+        Ok(query_as!(Feed, r#"
+SELECT f.* FROM feed f
+JOIN feed_label fl ON fl.feed_id = f.id
+JOIN label l ON l.id = fl.label_id
+WHERE l.name = $1
+ORDER BY f.url
+        "#, name)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn save_news_item(&self, ni: &NewsItem) -> anyhow::Result<(uuid::Uuid, SaveStatus)> {
+        let mut tx = self.pool.begin().await?;
+        let saved = Self::save_items(&mut tx, ni.feed_id, &[(ni.clone(), Vec::new())]).await?;
+        tx.commit().await?;
+        Ok(saved[0])
+    }
+
+    async fn save_feed_items(&self, feed: &Feed, items: &[(NewsItem, Vec<String>)]) -> anyhow::Result<(uuid::Uuid, Vec<(uuid::Uuid, SaveStatus)>)> {
+        let mut tx = self.pool.begin().await?;
+        let feed_id = Self::upsert_feed(&mut tx, feed).await?;
+        let saved = Self::save_items(&mut tx, feed_id, items).await?;
+        tx.commit().await?;
+        Ok((feed_id, saved))
+    }
+
+    async fn save_enclosure(&self, enclosure: &Enclosure) -> anyhow::Result<(uuid::Uuid, Option<String>)> {
+        let rec = sqlx::query!(r#"
+INSERT INTO enclosure (id, news_id, url, mime_type, length, duration, kind)
+VALUES ($1, $2, $3, $4, $5, $6, $7)
+ON CONFLICT (news_id, url) DO UPDATE SET mime_type = EXCLUDED.mime_type, length = EXCLUDED.length, duration = EXCLUDED.duration
+RETURNING id, content_path
+        "#,
+            enclosure.id, enclosure.news_id, enclosure.url, enclosure.mime_type, enclosure.length, enclosure.duration, enclosure.kind)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((rec.id, rec.content_path))
+    }
+
+    async fn set_enclosure_content_path(&self, id: uuid::Uuid, content_path: &str) -> anyhow::Result<()> {
+        sqlx::query!("UPDATE enclosure SET content_path = $2 WHERE id = $1", id, content_path)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_fetch_attempt(&self, attempt: &FetchAttempt) -> anyhow::Result<()> {
+        sqlx::query!(r#"
+INSERT INTO fetch_attempt (id, feed_id, url, started_timestamp, finished_timestamp, http_status, bytes, content_hash, format, new_items, error_class)
+VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        "#,
+            attempt.id, attempt.feed_id, attempt.url, attempt.started_timestamp, attempt.finished_timestamp, attempt.http_status,
+            attempt.bytes, attempt.content_hash, attempt.format, attempt.new_items, attempt.error_class)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_attempts_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<FetchAttempt>> {
+        Ok(query_as!(FetchAttempt, r#"SELECT * FROM fetch_attempt WHERE started_timestamp >= $1 ORDER BY started_timestamp"#, since)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
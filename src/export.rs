@@ -0,0 +1,187 @@
+//! `export!<path>`: the subscribed feeds as an OPML 2.0 file, in folders by label, that `opml!` imports
+//! back as it was. `export-by-type!<path>` uses the source types as folders instead. Without a path
+//! the OPML goes to stdout; `#<label>` after the path exports only the feeds with that label.
+
+use std::collections::HashMap;
+use std::fs;
+
+use chrono::Utc;
+use log::info;
+use opml::{Body, Head, OPML, Outline};
+
+use crate::models::FEED_RETIRED;
+use crate::storage::Storage;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum GroupBy {
+    /// A folder per label, nested on `/` (`Tech/Rust`); a feed with several labels is in several folders
+    Label,
+    /// A folder per source type
+    SourceType,
+}
+
+/// Write the OPML of all feeds but retired ones, or only those with label, to path, or to stdout when path is empty
+pub(crate) async fn run(db: &dyn Storage, path: &str, group_by: GroupBy, label: Option<&str>) -> anyhow::Result<()> {
+    let opml = build(db, group_by, label).await?;
+    let xml = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}\n", opml.to_string()?);
+    if path.is_empty() {
+        print!("{}", xml);
+    } else {
+        fs::write(path, xml)?;
+        info!("Exported OPML: {}", path);
+    }
+    Ok(())
+}
+
+pub(crate) async fn build(db: &dyn Storage, group_by: GroupBy, label: Option<&str>) -> anyhow::Result<OPML> {
+    let sources: HashMap<uuid::Uuid, _> = db.sources().await?.into_iter().map(|s| (s.id, s)).collect();
+    let mut feeds = match label {
+        Some(name) => db.feeds_by_label(name).await?,
+        None => db.feeds().await?,
+    };
+    feeds.retain(|f| f.status != FEED_RETIRED);
+    feeds.sort_by(|a, b| a.url.cmp(&b.url));
+    if feeds.is_empty() {
+        match label {
+            Some(name) => anyhow::bail!("No feeds labeled '{}' to export", name),
+            None => anyhow::bail!("No feeds to export"),
+        }
+    }
+
+    // the folders of each feed
+    let mut folders: HashMap<uuid::Uuid, Vec<String>> = HashMap::new();
+    match group_by {
+        GroupBy::Label => {
+            for label in db.labels().await? {
+                for feed in db.feeds_by_label(&label.name).await? {
+                    folders.entry(feed.id).or_default().push(label.name.clone());
+                }
+            }
+        }
+        GroupBy::SourceType => {
+            let types: HashMap<i32, String> = db.source_types().await?.into_iter().map(|t| (t.id, t.name)).collect();
+            for feed in &feeds {
+                if let Some(name) = sources.get(&feed.source_id).and_then(|s| types.get(&s.type_id)) {
+                    folders.insert(feed.id, vec![name.clone()]);
+                }
+            }
+        }
+    }
+
+    let mut outlines = Vec::new();
+    let mut unfiled = Vec::new();
+    for feed in &feeds {
+        let source = sources.get(&feed.source_id);
+        let name = source.map(|s| s.name.clone()).or(feed.title.clone()).unwrap_or(feed.url.clone());
+        let outline = Outline {
+            text: name.clone(),
+            title: Some(feed.title.clone().unwrap_or(name)),
+            r#type: Some("rss".to_string()),
+            xml_url: Some(feed.url.clone()),
+            html_url: source.map(|s| s.url.clone()),
+            ..Outline::default()
+        };
+        match folders.get(&feed.id) {
+            Some(paths) => {
+                for path in paths {
+                    folder(&mut outlines, &path.split('/').collect::<Vec<_>>()).push(outline.clone());
+                }
+            }
+            None => unfiled.push(outline),
+        }
+    }
+    outlines.extend(unfiled);
+
+    Ok(OPML {
+        version: "2.0".to_string(),
+        head: Some(Head {
+            title: Some("feed-fetcher subscriptions".to_string()),
+            date_created: Some(Utc::now().to_rfc2822()),
+            ..Head::default()
+        }),
+        body: Body { outlines },
+    })
+}
+
+/// The children of the folder at path under outlines, created as needed
+fn folder<'a>(outlines: &'a mut Vec<Outline>, path: &[&str]) -> &'a mut Vec<Outline> {
+    let Some((name, rest)) = path.split_first() else {
+        return outlines;
+    };
+    let index = match outlines.iter().position(|o| o.r#type.is_none() && o.text == *name) {
+        Some(index) => index,
+        None => {
+            outlines.push(Outline { text: name.to_string(), title: Some(name.to_string()), ..Outline::default() });
+            outlines.len() - 1
+        }
+    };
+    folder(&mut outlines[index].outlines, rest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::import::load_outlines;
+    use crate::memory::MemoryStorage;
+    use crate::models::{Feed, Source, WEBSITE_SOURCE_TYPE_ID};
+
+    use super::*;
+
+    async fn add_feed(db: &MemoryStorage, site: &str, labels: &[&str]) {
+        let source_id = db.save_source(&Source::new(site.to_string(), format!("https://{}/", site), WEBSITE_SOURCE_TYPE_ID)).await.unwrap();
+        let feed_id = db.save_feed(&Feed::new(source_id, format!("https://{}/feed.xml", site), None, None)).await.unwrap();
+        db.save_source_labels(source_id, &labels.iter().map(|l| l.to_string()).collect::<Vec<_>>()).await.unwrap();
+        db.save_feed_labels(feed_id, &labels.iter().map(|l| l.to_string()).collect::<Vec<_>>()).await.unwrap();
+    }
+
+    /// (feed url, site url, labels) of what `opml!` would import from the exported file, by feed url
+    async fn imported(db: &MemoryStorage, group_by: GroupBy, label: Option<&str>) -> Vec<(String, String, Vec<String>)> {
+        let path = std::env::temp_dir().join(format!("feed-fetcher-export-{}.opml", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        run(db, path, group_by, label).await.unwrap();
+        let outlines = load_outlines(path).await.unwrap();
+        fs::remove_file(path).unwrap();
+
+        let mut feeds: Vec<(String, String, Vec<String>)> = outlines.into_iter()
+            .map(|feed| {
+                let mut labels = feed.labels;
+                labels.sort();
+                (feed.outline.xml_url.unwrap(), feed.outline.html_url.unwrap(), labels)
+            })
+            .collect();
+        feeds.sort();
+        feeds
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let db = MemoryStorage::new();
+        add_feed(&db, "a.example", &["Tech/Rust", "Must Read"]).await;
+        add_feed(&db, "b.example", &["Tech"]).await;
+        add_feed(&db, "c.example", &[]).await;
+
+        let labels = |l: &[&str]| l.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        assert_eq!(imported(&db, GroupBy::Label, None).await, vec![
+            ("https://a.example/feed.xml".to_string(), "https://a.example/".to_string(), labels(&["Must Read", "Tech/Rust"])),
+            ("https://b.example/feed.xml".to_string(), "https://b.example/".to_string(), labels(&["Tech"])),
+            ("https://c.example/feed.xml".to_string(), "https://c.example/".to_string(), labels(&[])),
+        ]);
+
+        assert!(imported(&db, GroupBy::SourceType, None).await.iter().all(|(_, _, l)| *l == labels(&["Website"])));
+    }
+
+    #[tokio::test]
+    async fn test_label_filter() {
+        let db = MemoryStorage::new();
+        add_feed(&db, "a.example", &["Tech/Rust", "Must Read"]).await;
+        add_feed(&db, "b.example", &["Tech"]).await;
+        add_feed(&db, "c.example", &[]).await;
+
+        let feeds: Vec<String> = imported(&db, GroupBy::Label, Some("Tech/Rust")).await.into_iter().map(|(url, _, _)| url).collect();
+        assert_eq!(feeds, vec!["https://a.example/feed.xml".to_string()]);
+
+        let feeds: Vec<String> = imported(&db, GroupBy::SourceType, Some("Tech")).await.into_iter().map(|(url, _, _)| url).collect();
+        assert_eq!(feeds, vec!["https://b.example/feed.xml".to_string()]);
+
+        assert!(build(&db, GroupBy::Label, Some("Nope")).await.is_err());
+    }
+}
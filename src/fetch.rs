@@ -0,0 +1,365 @@
+//! HTTP fetching of feeds, pages and enclosures through one shared, configurable client
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::sync::OnceLock;
+
+use log::info;
+use reqwest::header::{CONTENT_TYPE, ETAG, HeaderMap, HeaderName, HeaderValue, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION};
+use reqwest::redirect::Policy;
+use reqwest::{Proxy, Response, StatusCode};
+use serde::Serialize;
+use url::Url;
+
+use crate::config::HttpConfig;
+use crate::limiter::{self, HostPermit};
+
+/// Redirects followed before a fetch gives up
+const MAX_REDIRECTS: usize = 10;
+
+static HTTP_CLIENT: OnceLock<HttpClient> = OnceLock::new();
+
+/// reqwest clients built from `HttpConfig`. Redirects are followed by hand (see `send`),
+/// so permanent moves can be told from temporary ones.
+pub(crate) struct HttpClient {
+    client: reqwest::Client,
+    /// Same settings without certificate verification, for sources with `skip_tls_verify`
+    insecure_client: reqwest::Client,
+    timeout: std::time::Duration,
+    download_timeout: std::time::Duration,
+    max_body_bytes: u64,
+    user_agent: String,
+}
+
+impl HttpClient {
+    pub fn new(config: &HttpConfig) -> anyhow::Result<Self> {
+        let build = |verify_tls: bool| -> anyhow::Result<reqwest::Client> {
+            let mut builder = reqwest::Client::builder()
+                .user_agent(config.user_agent.as_str())
+                .connect_timeout(config.connect_timeout)
+                .redirect(Policy::none())
+                .gzip(true)
+                .brotli(true)
+                .deflate(true)
+                .danger_accept_invalid_certs(!verify_tls);
+            if let Some(proxy) = &config.proxy {
+                builder = builder.proxy(Proxy::all(proxy)?);
+            }
+            Ok(builder.build()?)
+        };
+        Ok(Self {
+            client: build(true)?,
+            insecure_client: build(false)?,
+            timeout: config.timeout,
+            download_timeout: config.download_timeout,
+            max_body_bytes: config.max_body_bytes,
+            user_agent: config.user_agent.clone(),
+        })
+    }
+
+    fn client(&self, verify_tls: bool) -> &reqwest::Client {
+        if verify_tls { &self.client } else { &self.insecure_client }
+    }
+}
+
+/// Build the shared client; called at startup so a bad config fails early
+pub(crate) fn init(config: &HttpConfig) -> anyhow::Result<()> {
+    let client = HttpClient::new(config)?;
+    if HTTP_CLIENT.set(client).is_err() {
+        anyhow::bail!("HTTP client already initialized");
+    }
+    Ok(())
+}
+
+/// The shared client, with the default config unless `init` was called first
+fn http() -> &'static HttpClient {
+    HTTP_CLIENT.get_or_init(|| HttpClient::new(&HttpConfig::default()).expect("Unable to build the HTTP client"))
+}
+
+/// `FETCH_USER_AGENT`, for requests made outside the shared client (Playwright)
+pub(crate) fn user_agent() -> &'static str {
+    &http().user_agent
+}
+
+/// `FETCH_DOWNLOAD_TIMEOUT`, for downloads made outside the shared client (Playwright)
+pub(crate) fn download_timeout() -> std::time::Duration {
+    http().download_timeout
+}
+
+/// Outcome of a (possibly conditional) feed fetch
+pub(crate) enum FeedFetch {
+    /// The server answered 304; the feed has not changed since the cached validators were issued
+    NotModified,
+    /// The server answered 410; the feed was taken down for good
+    Gone,
+    Fetched(FeedResponse),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct FeedResponse {
+    /// Final URL after redirects
+    pub url: String,
+    /// Where the feed moved to, when the fetch started with permanent (301/308) redirects
+    pub permanent_url: Option<String>,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: String,
+}
+
+/// Fetch a feed, sending `If-None-Match` / `If-Modified-Since` when validators from a previous fetch are known
+pub(crate) async fn fetch_feed(url: &str, etag: Option<&str>, last_modified: Option<&str>, verify_tls: bool) -> anyhow::Result<FeedFetch> {
+    let mut headers = HeaderMap::new();
+    if let Some(etag) = etag {
+        headers.insert(IF_NONE_MATCH, HeaderValue::from_str(etag)?);
+    }
+    if let Some(last_modified) = last_modified {
+        headers.insert(IF_MODIFIED_SINCE, HeaderValue::from_str(last_modified)?);
+    }
+
+    let (response, permanent_url, _permit) = send(url, headers, verify_tls, http().timeout).await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        info!("Feed not modified: {}", url);
+        return Ok(FeedFetch::NotModified);
+    }
+    if response.status() == StatusCode::GONE {
+        info!("Feed gone: {}", url);
+        return Ok(FeedFetch::Gone);
+    }
+    let response = response.error_for_status()?;
+
+    let final_url = response.url().to_string();
+    let status = response.status().as_u16();
+    let headers = response.headers();
+    let content_type = header_value(headers, CONTENT_TYPE);
+    let etag = header_value(headers, ETAG);
+    let last_modified = header_value(headers, LAST_MODIFIED);
+    let body = read_body(response, http().max_body_bytes).await?;
+
+    Ok(FeedFetch::Fetched(FeedResponse { url: final_url, permanent_url, status, content_type, etag, last_modified, body }))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PageResponse {
+    /// Final URL after redirects
+    pub url: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+/// Fetch a web page; error statuses are returned, not treated as errors
+pub(crate) async fn fetch_page(url: &str, verify_tls: bool) -> anyhow::Result<PageResponse> {
+    let (response, _, _permit) = send(url, HeaderMap::new(), verify_tls, http().timeout).await?;
+    let final_url = response.url().to_string();
+    let status = response.status().as_u16();
+    let content_type = header_value(response.headers(), CONTENT_TYPE);
+    let body = read_body(response, http().max_body_bytes).await?;
+    Ok(PageResponse { url: final_url, status, content_type, body })
+}
+
+/// Send a GET, following redirects; returns the final response and, when the chain started with
+/// permanent (301/308) redirects, the url they lead to. timeout bounds each hop, body included.
+/// Every hop waits for its host's limiter; hold the returned permit until the body is read.
+async fn send(url: &str, headers: HeaderMap, verify_tls: bool, timeout: std::time::Duration) -> anyhow::Result<(Response, Option<String>, HostPermit)> {
+    let http = http();
+    let mut current = Url::parse(url)?;
+    let mut permanent_url = None;
+    let mut permanent = true;
+    let mut redirects = 0;
+    loop {
+        let permit = limiter::acquire(current.as_str()).await;
+        let response = http.client(verify_tls).get(current.clone()).headers(headers.clone()).timeout(timeout).send().await?;
+        let status = response.status();
+        if !status.is_redirection() || status == StatusCode::NOT_MODIFIED {
+            return Ok((response, permanent_url, permit));
+        }
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            anyhow::bail!("more than {} redirects", MAX_REDIRECTS);
+        }
+        let Some(location) = header_value(response.headers(), LOCATION) else {
+            anyhow::bail!("{} redirect without a Location header", status);
+        };
+        current = current.join(&location)?;
+        permanent &= is_permanent_redirect(status);
+        if permanent {
+            permanent_url = Some(current.to_string());
+        }
+        info!("Redirected ({}): {} -> {}", status.as_u16(), url, current);
+    }
+}
+
+fn is_permanent_redirect(status: StatusCode) -> bool {
+    status == StatusCode::MOVED_PERMANENTLY || status == StatusCode::PERMANENT_REDIRECT
+}
+
+/// Read a response body of at most max_bytes, decoded with the charset from its Content-Type (UTF-8 by default)
+async fn read_body(mut response: Response, max_bytes: u64) -> anyhow::Result<String> {
+    if let Some(length) = response.content_length().filter(|l| *l > max_bytes) {
+        anyhow::bail!("{} bytes is over the {} byte limit", length, max_bytes);
+    }
+    let encoding = header_value(response.headers(), CONTENT_TYPE)
+        .and_then(|ct| ct.split(';').find_map(|p| p.trim().strip_prefix("charset=").map(|c| c.trim_matches('"').to_string())))
+        .and_then(|charset| encoding_rs::Encoding::for_label(charset.as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if (body.len() + chunk.len()) as u64 > max_bytes {
+            anyhow::bail!("response exceeded the {} byte limit", max_bytes);
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let (text, _, _) = encoding.decode(&body);
+    Ok(text.into_owned())
+}
+
+/// HTTP status of a failed fetch, when the server answered at all
+pub(crate) fn error_status(e: &anyhow::Error) -> Option<u16> {
+    e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()).map(|s| s.as_u16())
+}
+
+/// Stream a file to disk, giving up once it grows past max_bytes or takes longer than `FETCH_DOWNLOAD_TIMEOUT`.
+/// The body goes to `<path>.part`, renamed to path only when complete, so a failed download leaves nothing behind.
+pub(crate) async fn download_file(url: &str, path: &str, max_bytes: u64, verify_tls: bool) -> anyhow::Result<u64> {
+    let (response, _, _permit) = send(url, HeaderMap::new(), verify_tls, http().download_timeout).await?;
+    let response = response.error_for_status()?;
+    if let Some(length) = response.content_length().filter(|l| *l > max_bytes) {
+        anyhow::bail!("{} bytes is over the {} byte limit", length, max_bytes);
+    }
+
+    let part_path = format!("{}.part", path);
+    let written = match write_body(response, &part_path, max_bytes).await {
+        Ok(written) => written,
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            return Err(e);
+        }
+    };
+    fs::rename(&part_path, path)?;
+    Ok(written)
+}
+
+async fn write_body(mut response: Response, path: &str, max_bytes: u64) -> anyhow::Result<u64> {
+    let mut file = File::create(path)?;
+    let mut written: u64 = 0;
+    while let Some(chunk) = response.chunk().await? {
+        written += chunk.len() as u64;
+        if written > max_bytes {
+            anyhow::bail!("download exceeded the {} byte limit", max_bytes);
+        }
+        file.write_all(&chunk)?;
+    }
+    Ok(written)
+}
+
+fn header_value(headers: &HeaderMap, name: HeaderName) -> Option<String> {
+    headers.get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    /// Serve one canned response (status line and headers, body) per path, for as long as the test
+    /// runs; `/echo-ua` answers with the request's User-Agent
+    async fn serve(routes: Vec<(&'static str, &'static str, &'static [u8])>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = [0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let path = request.split_whitespace().nth(1).unwrap_or_default().to_string();
+                let user_agent = request.lines()
+                    .find_map(|l| l.strip_prefix("user-agent: "))
+                    .unwrap_or_default()
+                    .to_string();
+                let (head, body) = match routes.iter().find(|(p, _, _)| *p == path) {
+                    Some((_, head, body)) => (head.to_string(), body.to_vec()),
+                    None if path == "/echo-ua" => ("HTTP/1.1 200 OK\r\n".to_string(), user_agent.into_bytes()),
+                    None => ("HTTP/1.1 404 Not Found\r\n".to_string(), Vec::new()),
+                };
+                let head = format!("{}Content-Length: {}\r\nConnection: close\r\n\r\n", head, body.len());
+                socket.write_all(head.as_bytes()).await.unwrap();
+                socket.write_all(&body).await.unwrap();
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn test_permanent_redirects() {
+        let base = serve(vec![
+            ("/old", "HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\n", b""),
+            ("/new", "HTTP/1.1 302 Found\r\nLocation: /today\r\n", b""),
+            ("/today", "HTTP/1.1 200 OK\r\n", b"<rss/>"),
+            ("/gone", "HTTP/1.1 410 Gone\r\n", b""),
+        ]).await;
+
+        let Ok(FeedFetch::Fetched(response)) = fetch_feed(&format!("{}/old", base), None, None, true).await else {
+            panic!("Expected a response");
+        };
+        assert_eq!(response.url, format!("{}/today", base));
+        // only the 301 is permanent
+        assert_eq!(response.permanent_url, Some(format!("{}/new", base)));
+        assert_eq!(response.body, "<rss/>");
+
+        let Ok(FeedFetch::Fetched(response)) = fetch_feed(&format!("{}/new", base), None, None, true).await else {
+            panic!("Expected a response");
+        };
+        assert_eq!(response.permanent_url, None);
+
+        assert!(matches!(fetch_feed(&format!("{}/gone", base), None, None, true).await, Ok(FeedFetch::Gone)));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_page() {
+        let base = serve(vec![
+            ("/latin1", "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=ISO-8859-1\r\n", b"caf\xe9"),
+            ("/missing", "HTTP/1.1 404 Not Found\r\n", b"nope"),
+        ]).await;
+
+        assert_eq!(fetch_page(&format!("{}/latin1", base), true).await.unwrap().body, "caf\u{e9}");
+        assert_eq!(fetch_page(&format!("{}/missing", base), true).await.unwrap().status, 404);
+        let user_agent = fetch_page(&format!("{}/echo-ua", base), true).await.unwrap().body;
+        assert!(user_agent.starts_with("feed-fetcher/"), "{}", user_agent);
+
+        let response = reqwest::get(format!("{}/latin1", base)).await.unwrap();
+        assert!(read_body(response, 3).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_download_file_leaves_nothing_on_failure() {
+        let base = serve(vec![("/file", "HTTP/1.1 200 OK\r\n", b"enclosure")]).await;
+        // promises more than it sends, then hangs up
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let truncated = format!("http://{}/file", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nenclo").await.unwrap();
+        });
+
+        let dir = std::env::temp_dir().join(format!("feed-fetcher-download-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("file").to_str().unwrap().to_string();
+        assert_eq!(download_file(&format!("{}/file", base), &path, 100, true).await.unwrap(), 9);
+        assert_eq!(fs::read(&path).unwrap(), b"enclosure");
+
+        fs::remove_file(&path).unwrap();
+        assert!(download_file(&truncated, &path, 100, true).await.is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,163 @@
+//! Feed health: consecutive failures back off the next poll exponentially, and feeds that keep
+//! failing or stop publishing are marked dead. `dead!` lists them, `reactivate!<url|all>` revives them.
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+
+use crate::config::HealthConfig;
+use crate::models::{Feed, FEED_ACTIVE, FEED_DEAD, FetchAttempt, FetchError};
+use crate::report::format_timestamp;
+use crate::storage::Storage;
+
+/// Longest a failing feed is backed off between polls
+const MAX_BACKOFF_HOURS: i64 = 24;
+
+/// Delay before retrying a feed that failed `failures` times in a row: its ttl, doubled per failure
+pub(crate) fn backoff(ttl_minutes: i32, failures: i32) -> Duration {
+    let doublings = failures.saturating_sub(1).clamp(0, 16) as u32;
+    let minutes = i64::from(ttl_minutes.max(1)).saturating_mul(1 << doublings);
+    Duration::minutes(minutes).min(Duration::hours(MAX_BACKOFF_HOURS))
+}
+
+/// Update a feed's health after a poll; returns true when this poll got it marked dead.
+/// Local failures (disk, database) aren't held against the feed.
+pub(crate) fn record_poll(feed: &mut Feed, attempt: &FetchAttempt, config: &HealthConfig, ttl_minutes: i32, now: DateTime<Utc>) -> bool {
+    let local_errors = [FetchError::Storage.as_str(), FetchError::Io.as_str()];
+    if attempt.error_class.as_deref().is_some_and(|e| local_errors.contains(&e)) {
+        return false;
+    }
+
+    if attempt.is_success() {
+        feed.consecutive_failures = 0;
+        feed.next_poll_timestamp = None;
+        feed.last_success_timestamp = Some(now);
+        if attempt.new_items.unwrap_or_default() > 0 {
+            feed.last_new_item_timestamp = Some(now);
+        }
+    } else {
+        feed.consecutive_failures += 1;
+        feed.next_poll_timestamp = Some(now + backoff(ttl_minutes, feed.consecutive_failures));
+    }
+
+    if feed.status != FEED_ACTIVE {
+        return false;
+    }
+    // new feeds and reactivated ones get a full grace period
+    let quiet_since = [feed.last_new_item_timestamp, feed.status_timestamp].into_iter().flatten()
+        .fold(feed.create_timestamp, DateTime::max);
+    if feed.consecutive_failures >= config.dead_after_failures {
+        set_status(feed, FEED_DEAD, Some(format!("{} consecutive failures", feed.consecutive_failures)), now);
+        true
+    } else if now - quiet_since >= Duration::days(config.dead_after_days) {
+        set_status(feed, FEED_DEAD, Some(format!("no new items in {} days", config.dead_after_days)), now);
+        true
+    } else {
+        false
+    }
+}
+
+pub(crate) fn set_status(feed: &mut Feed, status: &str, reason: Option<String>, now: DateTime<Utc>) {
+    feed.status = status.to_string();
+    feed.status_reason = reason;
+    feed.status_timestamp = Some(now);
+}
+
+/// Print the dead feeds
+pub(crate) async fn list_dead(db: &dyn Storage) -> anyhow::Result<()> {
+    let dead: Vec<Feed> = db.feeds().await?.into_iter().filter(|f| f.status == FEED_DEAD).collect();
+    println!("{:<16}  {:>8}  {:<16}  {:<28}  URL", "DEAD SINCE", "FAILURES", "LAST SUCCESS", "REASON");
+    for feed in &dead {
+        println!("{:<16}  {:>8}  {:<16}  {:<28}  {}",
+                 format_timestamp(feed.status_timestamp),
+                 feed.consecutive_failures,
+                 format_timestamp(feed.last_success_timestamp),
+                 feed.status_reason.as_deref().unwrap_or("-"),
+                 feed.url);
+    }
+    println!("{} dead feeds", dead.len());
+    Ok(())
+}
+
+/// Mark a dead feed (or, with "all", every dead feed) active again and poll it on the next run
+pub(crate) async fn reactivate(db: &dyn Storage, which: &str) -> anyhow::Result<()> {
+    let feeds: Vec<Feed> = db.feeds().await?.into_iter()
+        .filter(|f| f.status == FEED_DEAD && (which == "all" || f.url == which))
+        .collect();
+    if feeds.is_empty() {
+        warn!("No dead feed matches: {}", which);
+    }
+    let now = Utc::now();
+    for mut feed in feeds {
+        set_status(&mut feed, FEED_ACTIVE, None, now);
+        feed.consecutive_failures = 0;
+        feed.next_poll_timestamp = None;
+        db.save_feed_health(&feed).await?;
+        info!("Reactivated feed: {}", feed.url);
+        println!("Reactivated {}", feed.url);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: HealthConfig = HealthConfig { dead_after_failures: 3, dead_after_days: 30 };
+
+    fn attempt(error: Option<FetchError>, new_items: i32) -> FetchAttempt {
+        let mut attempt = FetchAttempt::start("https://example.org/feed".to_string());
+        attempt.new_items = Some(new_items);
+        if let Some(error) = error {
+            attempt.fail(error);
+        }
+        attempt
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(60, 1), Duration::minutes(60));
+        assert_eq!(backoff(60, 3), Duration::minutes(240));
+        assert_eq!(backoff(60, 30), Duration::hours(MAX_BACKOFF_HOURS));
+    }
+
+    #[test]
+    fn test_dead_after_failures() {
+        let now = Utc::now();
+        let mut feed = Feed::new(uuid::Uuid::new_v4(), "https://example.org/feed".to_string(), None, None);
+        assert!(!record_poll(&mut feed, &attempt(Some(FetchError::Network), 0), &CONFIG, 60, now));
+        assert!(!record_poll(&mut feed, &attempt(Some(FetchError::Http), 0), &CONFIG, 60, now));
+        assert_eq!(feed.next_poll_timestamp, Some(now + Duration::minutes(120)));
+
+        // a local failure neither counts nor resets the streak
+        assert!(!record_poll(&mut feed, &attempt(Some(FetchError::Storage), 0), &CONFIG, 60, now));
+        assert_eq!(feed.consecutive_failures, 2);
+
+        assert!(record_poll(&mut feed, &attempt(Some(FetchError::Parse), 0), &CONFIG, 60, now));
+        assert_eq!(feed.status, FEED_DEAD);
+        assert_eq!(feed.status_reason.as_deref(), Some("3 consecutive failures"));
+    }
+
+    #[test]
+    fn test_success_resets_failures() {
+        let now = Utc::now();
+        let mut feed = Feed::new(uuid::Uuid::new_v4(), "https://example.org/feed".to_string(), None, None);
+        record_poll(&mut feed, &attempt(Some(FetchError::Network), 0), &CONFIG, 60, now);
+        record_poll(&mut feed, &attempt(None, 2), &CONFIG, 60, now);
+        assert_eq!((feed.consecutive_failures, feed.next_poll_timestamp), (0, None));
+        assert_eq!(feed.last_new_item_timestamp, Some(now));
+    }
+
+    #[test]
+    fn test_dead_after_days_without_new_items() {
+        let mut feed = Feed::new(uuid::Uuid::new_v4(), "https://example.org/feed".to_string(), None, None);
+        let later = feed.create_timestamp + Duration::days(29);
+        assert!(!record_poll(&mut feed, &attempt(None, 0), &CONFIG, 60, later));
+        let later = feed.create_timestamp + Duration::days(31);
+        assert!(record_poll(&mut feed, &attempt(None, 0), &CONFIG, 60, later));
+        assert_eq!(feed.status_reason.as_deref(), Some("no new items in 30 days"));
+
+        // reactivating restarts the clock
+        set_status(&mut feed, FEED_ACTIVE, None, later);
+        assert!(!record_poll(&mut feed, &attempt(None, 0), &CONFIG, 60, later + Duration::days(1)));
+    }
+}
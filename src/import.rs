@@ -0,0 +1,310 @@
+//! `opml!` import: the feeds of an OPML file or url, and of the OPML documents it includes, are
+//! fetched concurrently, each in isolation, and summarized in a table once they're all done.
+//! The folders a feed is listed under become labels of its source and feed.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::panic::AssertUnwindSafe;
+use std::path::Path;
+
+use anyhow::Context;
+use futures::{FutureExt, StreamExt};
+use log::{error, info, warn};
+use opml::{OPML, Outline};
+use url::Url;
+
+use crate::config::HealthConfig;
+use crate::fetch;
+use crate::models::{self, SaveCounts, Source};
+use crate::storage::Storage;
+
+/// A feed outline and the labels from the folders it's listed under, e.g. `Tech/Rust` for a
+/// feed in the "Rust" folder of the "Tech" folder
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FeedOutline {
+    pub outline: Outline,
+    pub labels: Vec<String>,
+}
+
+/// What importing one outline came to
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ImportResult {
+    pub feed_url: String,
+    /// Items saved, or why the feed couldn't be imported
+    pub outcome: Result<SaveCounts, String>,
+}
+
+/// The rss outlines of the OPML document at location (a path or an http(s) url) and of every document
+/// it includes through `type="include"` outlines. Each document is read once, so include cycles end;
+/// an include that can't be loaded is skipped.
+pub(crate) async fn load_outlines(location: &str) -> anyhow::Result<Vec<FeedOutline>> {
+    let mut outlines: Vec<FeedOutline> = Vec::new();
+    // index into outlines by feed url
+    let mut feed_urls: HashMap<String, usize> = HashMap::new();
+    let mut loaded = HashSet::new();
+    let mut pending = VecDeque::from([resolve("", location)?]);
+    while let Some(location) = pending.pop_front() {
+        if !loaded.insert(location.clone()) {
+            warn!("OPML already included, skipping: {}", location);
+            continue;
+        }
+        let opml = match read_opml(&location).await {
+            Ok(opml) => opml,
+            Err(e) if loaded.len() > 1 => {
+                error!("Unable to load included OPML: {}; {}", location, e);
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut includes = Vec::new();
+        for outline in &opml.body.outlines {
+            let mut found = Vec::new();
+            collect_outlines(outline, &[], &mut found);
+            // the same feed listed twice is imported once, with the labels of both listings
+            for feed in found {
+                match feed.outline.xml_url.as_ref().and_then(|u| feed_urls.get(u)) {
+                    Some(&i) => {
+                        let labels = &mut outlines[i].labels;
+                        labels.extend(feed.labels.into_iter().filter(|l| !labels.contains(l)).collect::<Vec<_>>());
+                    }
+                    None => {
+                        if let Some(url) = &feed.outline.xml_url {
+                            feed_urls.insert(url.clone(), outlines.len());
+                        }
+                        outlines.push(feed);
+                    }
+                }
+            }
+            collect_includes(outline, &mut includes);
+        }
+        for include in includes {
+            match resolve(&location, &include) {
+                Ok(included) => pending.push_back(included),
+                Err(e) => error!("Invalid OPML include in {}: {}; {}", location, include, e),
+            }
+        }
+    }
+    Ok(outlines)
+}
+
+async fn read_opml(location: &str) -> anyhow::Result<OPML> {
+    let body = if location.starts_with("http") {
+        info!("Fetching OPML: {}", location);
+        let page = fetch::fetch_page(location, true).await?;
+        if page.status >= 400 {
+            anyhow::bail!("HTTP {}", page.status);
+        }
+        page.body
+    } else {
+        fs::read_to_string(location)?
+    };
+    // some exports start with a byte order mark
+    OPML::from_str(body.trim_start_matches('\u{feff}')).with_context(|| format!("Unable to parse OPML: {}", location))
+}
+
+/// Where an include's url points, relative to the document it appears in; paths are made canonical
+/// so a document is recognized however it's reached
+fn resolve(base: &str, href: &str) -> anyhow::Result<String> {
+    if let Ok(url) = Url::parse(href) {
+        return Ok(url.to_string());
+    }
+    if base.starts_with("http") {
+        return Ok(Url::parse(base)?.join(href)?.to_string());
+    }
+    let path = Path::new(base).parent().unwrap_or(Path::new("")).join(href);
+    Ok(fs::canonicalize(&path).unwrap_or(path).to_string_lossy().to_string())
+}
+
+/// The rss outlines of outline and its children, labeled with the folders they're in;
+/// folders are the outlines with children, `folders` are the ones outline is in
+pub(crate) fn collect_outlines(outline: &Outline, folders: &[String], outlines: &mut Vec<FeedOutline>) {
+    if outline.r#type.as_deref() == Some("rss") {
+        let labels = if folders.is_empty() { Vec::new() } else { vec![folders.join("/")] };
+        outlines.push(FeedOutline { outline: outline.clone(), labels });
+    }
+    if outline.outlines.is_empty() {
+        return;
+    }
+    let mut folders = folders.to_vec();
+    let name = outline.title.as_deref().filter(|t| !t.is_empty()).unwrap_or(&outline.text);
+    if !name.is_empty() && outline.r#type.is_none() {
+        folders.push(name.replace('/', "-"));
+    }
+    for child in &outline.outlines {
+        collect_outlines(child, &folders, outlines);
+    }
+}
+
+/// The urls of the `type="include"` outlines of outline and its children
+fn collect_includes(outline: &Outline, includes: &mut Vec<String>) {
+    if outline.r#type.as_deref() == Some("include") {
+        match &outline.url {
+            Some(url) => includes.push(url.clone()),
+            None => warn!("OPML include without a url: {}", outline.text),
+        }
+    }
+    for child in &outline.outlines {
+        collect_includes(child, includes);
+    }
+}
+
+/// Import outlines with at most `workers` at a time; an error or panic only fails its own outline
+pub(crate) async fn import_outlines(dir_path: &str, outlines: Vec<FeedOutline>, workers: usize, db: &dyn Storage, health: &HealthConfig) -> Vec<ImportResult> {
+    futures::stream::iter(outlines)
+        .map(|feed| async move {
+            let feed_url = feed.outline.xml_url.clone().unwrap_or(feed.outline.text.clone());
+            let outcome = match AssertUnwindSafe(import_outline(dir_path, &feed, db, health)).catch_unwind().await {
+                Ok(Ok(counts)) => Ok(counts),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(panic) => Err(panic_message(panic.as_ref())),
+            };
+            if let Err(e) = &outcome {
+                error!("Error importing feed: {}; {}", feed_url, e);
+            }
+            ImportResult { feed_url, outcome }
+        })
+        .buffer_unordered(workers.max(1))
+        .collect()
+        .await
+}
+
+async fn import_outline(dir_path: &str, feed: &FeedOutline, db: &dyn Storage, health: &HealthConfig) -> anyhow::Result<SaveCounts> {
+    let outline = &feed.outline;
+    info!("processing: {:?}", outline);
+    let Some(feed_url) = outline.xml_url.clone() else {
+        anyhow::bail!("outline has no xmlUrl");
+    };
+    let Some(html_url) = outline.html_url.clone() else {
+        anyhow::bail!("outline has no htmlUrl");
+    };
+
+    let source = Source::new(outline.text.clone(), html_url, models::WEBSITE_SOURCE_TYPE_ID);
+    let source_id = source.save(db).await.with_context(|| format!("Error saving source: {}", source.url))?;
+    db.save_source_labels(source_id, &feed.labels).await.with_context(|| format!("Error saving labels of source: {}", source.url))?;
+    let feed_dir = crate::create_feed_dir(dir_path, &feed_url).await;
+    let counts = match crate::poll_and_record(source_id, &feed_url, &feed_dir, db, health).await {
+        (Ok(counts), None) => counts,
+        (Ok(_), Some(error_class)) => anyhow::bail!("fetch failed ({})", error_class),
+        (Err(e), _) => anyhow::bail!("Error saving feed: {}; {}", feed_url, e),
+    };
+    // the feed row exists once it has been fetched
+    if let Some(stored) = db.feed_by_url(&feed_url).await? {
+        db.save_feed_labels(stored.id, &feed.labels).await.with_context(|| format!("Error saving labels of feed: {}", feed_url))?;
+    }
+    Ok(counts)
+}
+
+fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_default();
+    format!("panicked: {}", message)
+}
+
+/// Print one row per feed, failures first, and the totals
+pub(crate) fn print_summary(results: &[ImportResult]) {
+    let mut results: Vec<&ImportResult> = results.iter().collect();
+    results.sort_by(|a, b| a.outcome.is_ok().cmp(&b.outcome.is_ok()).then(a.feed_url.cmp(&b.feed_url)));
+
+    println!("{:<6}  {:>5}  {:>7}  {:>9}  {:<40}  URL", "STATUS", "NEW", "UPDATED", "UNCHANGED", "ERROR");
+    for result in &results {
+        match &result.outcome {
+            Ok(counts) => println!("{:<6}  {:>5}  {:>7}  {:>9}  {:<40}  {}",
+                                   "ok", counts.inserted, counts.updated, counts.unchanged, "-", result.feed_url),
+            Err(e) => println!("{:<6}  {:>5}  {:>7}  {:>9}  {:<40}  {}",
+                               "failed", "-", "-", "-", e, result.feed_url),
+        }
+    }
+
+    let imported: Vec<&SaveCounts> = results.iter().filter_map(|r| r.outcome.as_ref().ok()).collect();
+    println!("{} imported, {} failed, {} new items",
+             imported.len(), results.len() - imported.len(), imported.iter().map(|c| c.inserted).sum::<usize>());
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::MemoryStorage;
+
+    use super::*;
+
+    fn outline(text: &str, xml_url: Option<&str>) -> Outline {
+        Outline {
+            text: text.to_string(),
+            r#type: Some("rss".to_string()),
+            xml_url: xml_url.map(|u| u.to_string()),
+            ..Outline::default()
+        }
+    }
+
+    fn feed(text: &str, xml_url: Option<&str>) -> FeedOutline {
+        FeedOutline { outline: outline(text, xml_url), labels: vec!["News".to_string()] }
+    }
+
+    #[test]
+    fn test_collect_outlines() {
+        let rust = Outline { text: "Rust".to_string(), outlines: vec![outline("b", Some("https://example.org/b.xml"))], ..Outline::default() };
+        let news = Outline {
+            text: "News".to_string(),
+            outlines: vec![outline("a", Some("https://example.org/a.xml")), Outline::default(), rust],
+            ..Outline::default()
+        };
+        let mut outlines = Vec::new();
+        collect_outlines(&news, &[], &mut outlines);
+        let found: Vec<(&str, Vec<String>)> = outlines.iter().map(|o| (o.outline.text.as_str(), o.labels.clone())).collect();
+        assert_eq!(found, vec![("a", vec!["News".to_string()]), ("b", vec!["News/Rust".to_string()])]);
+    }
+
+    #[tokio::test]
+    async fn test_load_outlines_follows_includes() {
+        let dir = std::env::temp_dir().join(format!("feed-fetcher-opml-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("more")).unwrap();
+        let opml = |outlines: &str| format!(r#"<opml version="2.0"><head/><body>{}</body></opml>"#, outlines);
+        fs::write(dir.join("main.opml"), opml(r#"
+            <outline text="a" type="rss" xmlUrl="https://example.org/a.xml"/>
+            <outline text="more" type="include" url="more/more.opml"/>"#)).unwrap();
+        // includes its includer (a cycle), a missing document and a feed already listed
+        fs::write(dir.join("more/more.opml"), opml(r#"
+            <outline text="b" type="rss" xmlUrl="https://example.org/b.xml"/>
+            <outline text="Favorites"><outline text="a" type="rss" xmlUrl="https://example.org/a.xml"/></outline>
+            <outline text="main" type="include" url="../main.opml"/>
+            <outline text="gone" type="include" url="missing.opml"/>"#)).unwrap();
+
+        let outlines = load_outlines(dir.join("main.opml").to_str().unwrap()).await.unwrap();
+        assert_eq!(outlines.iter().map(|o| o.outline.text.as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+        assert_eq!(outlines[0].labels, vec!["Favorites".to_string()]);
+        assert!(load_outlines(dir.join("missing.opml").to_str().unwrap()).await.is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(resolve("https://example.org/lists/all.opml", "tech.opml").unwrap(), "https://example.org/lists/tech.opml");
+        assert_eq!(resolve("/tmp/all.opml", "https://example.com/x.opml").unwrap(), "https://example.com/x.opml");
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_isolated() {
+        let db = MemoryStorage::new();
+        let outlines = vec![feed("no url", None), feed("no site", Some("https://example.org/b.xml"))];
+        let results = import_outlines("downloads/test", outlines, 2, &db, &HealthConfig::default()).await;
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|r| r.outcome.is_err()));
+        assert_eq!(panic_message(&"boom"), "panicked: boom");
+    }
+
+    #[tokio::test]
+    async fn test_import_labels_source() {
+        let db = MemoryStorage::new();
+        let mut unreachable = feed("down", Some("http://127.0.0.1:1/feed.xml"));
+        unreachable.outline.html_url = Some("http://127.0.0.1:1/".to_string());
+        let dir = std::env::temp_dir().join(format!("feed-fetcher-import-{}", uuid::Uuid::new_v4()));
+        let results = import_outlines(dir.to_str().unwrap(), vec![unreachable], 1, &db, &HealthConfig::default()).await;
+        assert_eq!(results[0].outcome, Err("fetch failed (network)".to_string()));
+
+        let labeled = db.sources_by_label("News").await.unwrap();
+        assert_eq!(labeled.iter().map(|s| s.url.as_str()).collect::<Vec<_>>(), vec!["http://127.0.0.1:1/"]);
+        assert!(db.feeds_by_label("News").await.unwrap().is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,101 @@
+//! [JSON Feed](https://jsonfeed.org/) 1.0 / 1.1
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JsonFeed {
+    pub version: String,
+    pub title: String,
+    pub home_page_url: Option<String>,
+    pub feed_url: Option<String>,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub items: Vec<JsonFeedItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JsonFeedItem {
+    /// Required by the spec to be a string, but some publishers emit numbers
+    #[serde(deserialize_with = "deserialize_id")]
+    pub id: String,
+    pub url: Option<String>,
+    pub external_url: Option<String>,
+    pub title: Option<String>,
+    pub content_html: Option<String>,
+    pub content_text: Option<String>,
+    pub summary: Option<String>,
+    pub date_published: Option<String>,
+    pub date_modified: Option<String>,
+    /// 1.1
+    #[serde(default)]
+    pub authors: Vec<JsonFeedAuthor>,
+    /// 1.0, deprecated in 1.1
+    pub author: Option<JsonFeedAuthor>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub attachments: Vec<JsonFeedAttachment>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JsonFeedAuthor {
+    pub name: Option<String>,
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct JsonFeedAttachment {
+    pub url: String,
+    pub mime_type: String,
+    pub title: Option<String>,
+    pub size_in_bytes: Option<i64>,
+    pub duration_in_seconds: Option<f64>,
+}
+
+/// Parse a JSON Feed document; anything without a jsonfeed.org `version` is rejected
+pub(crate) fn parse(content: &str) -> anyhow::Result<JsonFeed> {
+    let feed: JsonFeed = serde_json::from_str(content)?;
+    if !feed.version.starts_with("https://jsonfeed.org/version/") {
+        anyhow::bail!("Unknown JSON Feed version: {}", feed.version);
+    }
+    Ok(feed)
+}
+
+fn deserialize_id<'de, D>(deserializer: D) -> Result<String, D::Error>
+    where D: Deserializer<'de>
+{
+    match serde_json::Value::deserialize(deserializer)? {
+        serde_json::Value::String(s) => Ok(s),
+        serde_json::Value::Number(n) => Ok(n.to_string()),
+        other => Err(serde::de::Error::custom(format!("invalid item id: {}", other))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_json_feed() {
+        let content = r#"{
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": "My Example Feed",
+            "home_page_url": "https://example.org/",
+            "items": [
+                { "id": "2", "content_text": "This is a second item.", "url": "https://example.org/second-item" },
+                { "id": 1, "title": "First", "url": "https://example.org/initial-post", "date_published": "2023-06-19T10:00:00Z" }
+            ]
+        }"#;
+        let feed = parse(content).unwrap();
+        assert_eq!(feed.title, "My Example Feed");
+        assert_eq!(feed.items.len(), 2);
+        assert_eq!(feed.items[1].id, "1");
+        assert_eq!(feed.items[1].date_published.as_deref(), Some("2023-06-19T10:00:00Z"));
+    }
+
+    #[test]
+    fn test_parse_rejects_other_json() {
+        assert!(parse(r#"{"version": "1.0", "title": "not a feed"}"#).is_err());
+        assert!(parse("<rss></rss>").is_err());
+    }
+}
@@ -0,0 +1,33 @@
+//! `labels!`: every label (such as the folders of imported OPML files) with how many sources and
+//! feeds carry it; `labels!<name>` lists the sources and feeds carrying one
+
+use crate::storage::Storage;
+
+pub(crate) async fn run(db: &dyn Storage, name: &str) -> anyhow::Result<()> {
+    if !name.is_empty() {
+        return list_labeled(db, name).await;
+    }
+    let labels = db.labels().await?;
+    println!("{:>7}  {:>5}  LABEL", "SOURCES", "FEEDS");
+    for label in &labels {
+        let sources = db.sources_by_label(&label.name).await?;
+        let feeds = db.feeds_by_label(&label.name).await?;
+        println!("{:>7}  {:>5}  {}", sources.len(), feeds.len(), label.name);
+    }
+    println!("{} labels", labels.len());
+    Ok(())
+}
+
+async fn list_labeled(db: &dyn Storage, name: &str) -> anyhow::Result<()> {
+    let sources = db.sources_by_label(name).await?;
+    let feeds = db.feeds_by_label(name).await?;
+    println!("{:<6}  {:<8}  URL", "KIND", "STATUS");
+    for source in &sources {
+        println!("{:<6}  {:<8}  {}", "source", "-", source.url);
+    }
+    for feed in &feeds {
+        println!("{:<6}  {:<8}  {}", "feed", feed.status, feed.url);
+    }
+    println!("{} sources and {} feeds labeled {}", sources.len(), feeds.len(), name);
+    Ok(())
+}
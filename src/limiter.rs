@@ -0,0 +1,283 @@
+//! Per-host politeness shared by feed fetches, article downloads and Playwright navigations:
+//! a token bucket spaces requests out and a semaphore caps how many are in flight at once
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use log::info;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+use crate::config::HostLimits;
+use crate::models::Source;
+
+static LIMITER: OnceLock<HostLimiter> = OnceLock::new();
+
+struct Host {
+    limits: Mutex<HostLimits>,
+    in_flight: Arc<Semaphore>,
+    /// Permits to forget as they come back, after the cap was lowered while they were in use
+    shrink_by: AtomicUsize,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Host {
+    fn new(limits: HostLimits) -> Self {
+        Self {
+            limits: Mutex::new(limits),
+            in_flight: Arc::new(Semaphore::new(limits.max_in_flight.max(1))),
+            shrink_by: AtomicUsize::new(0),
+            bucket: Mutex::new(Bucket { tokens: f64::from(limits.burst.max(1)), refilled: Instant::now() }),
+        }
+    }
+
+    fn limits(&self) -> HostLimits {
+        *self.limits.lock().expect("host limits lock poisoned")
+    }
+
+    /// Change the limits in place, so requests in flight keep counting against the new cap
+    fn resize(&self, limits: HostLimits) {
+        let mut current = self.limits.lock().expect("host limits lock poisoned");
+        let (old, new) = (current.max_in_flight.max(1), limits.max_in_flight.max(1));
+        if new > old {
+            // cancel what is still owed from an earlier shrink before adding permits
+            let mut grow = new - old;
+            let owed = self.shrink_by.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| Some(owed.saturating_sub(grow)));
+            grow -= owed.unwrap_or_default().min(grow);
+            self.in_flight.add_permits(grow);
+        } else if new < old {
+            let shrink = old - new;
+            let free = self.in_flight.available_permits().min(shrink);
+            if let Ok(permits) = self.in_flight.try_acquire_many(free as u32) {
+                permits.forget();
+            }
+            self.shrink_by.fetch_add(shrink - free, Ordering::SeqCst);
+        }
+        let mut bucket = self.bucket.lock().expect("host bucket lock poisoned");
+        bucket.tokens = bucket.tokens.min(f64::from(limits.burst.max(1)));
+        *current = limits;
+    }
+
+    /// Whether a returning permit should be forgotten to finish shrinking the cap
+    fn take_shrink(&self) -> bool {
+        self.shrink_by.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |owed| owed.checked_sub(1)).is_ok()
+    }
+
+    /// Take a token, or say how long until one is available
+    fn take_token(&self, now: Instant) -> Option<Duration> {
+        let limits = self.limits();
+        let rate = limits.requests_per_second;
+        if rate <= 0.0 {
+            return None;
+        }
+        let mut bucket = self.bucket.lock().expect("host bucket lock poisoned");
+        let elapsed = now.saturating_duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(f64::from(limits.burst.max(1)));
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Held for the duration of a request; dropping it frees the host's in-flight slot
+pub(crate) struct HostPermit {
+    permit: Option<(OwnedSemaphorePermit, Arc<Host>)>,
+}
+
+impl Drop for HostPermit {
+    fn drop(&mut self) {
+        if let Some((permit, host)) = self.permit.take() {
+            if host.take_shrink() {
+                permit.forget();
+            }
+        }
+    }
+}
+
+/// The strictest of two limits; a rate of 0 (unlimited) is the loosest
+fn strictest(a: HostLimits, b: HostLimits) -> HostLimits {
+    let rate = |r: f64| if r > 0.0 { r } else { f64::INFINITY };
+    let requests_per_second = rate(a.requests_per_second).min(rate(b.requests_per_second));
+    HostLimits {
+        requests_per_second: if requests_per_second.is_finite() { requests_per_second } else { 0.0 },
+        burst: a.burst.min(b.burst),
+        max_in_flight: a.max_in_flight.min(b.max_in_flight),
+    }
+}
+
+pub(crate) struct HostLimiter {
+    default: HostLimits,
+    hosts: Mutex<HashMap<String, Arc<Host>>>,
+    /// Limits of the sources that override them, by host and source id
+    overrides: Mutex<HashMap<String, HashMap<uuid::Uuid, HostLimits>>>,
+}
+
+impl HostLimiter {
+    pub fn new(default: HostLimits) -> Self {
+        Self { default, hosts: Mutex::new(HashMap::new()), overrides: Mutex::new(HashMap::new()) }
+    }
+
+    fn host(&self, name: &str) -> Arc<Host> {
+        let mut hosts = self.hosts.lock().expect("host limiter lock poisoned");
+        hosts.entry(name.to_string()).or_insert_with(|| Arc::new(Host::new(self.default))).clone()
+    }
+
+    /// Set or, with None, clear a source's limits for a host. When several sources share the host
+    /// the strictest of their limits applies; without any, the defaults do.
+    pub fn set_source_limits(&self, name: &str, source_id: uuid::Uuid, limits: Option<HostLimits>) {
+        let mut overrides = self.overrides.lock().expect("host limiter lock poisoned");
+        if limits.is_none() && !overrides.contains_key(name) {
+            return;
+        }
+        let sources = overrides.entry(name.to_string()).or_default();
+        match limits {
+            Some(limits) => sources.insert(source_id, limits),
+            None => sources.remove(&source_id),
+        };
+        let effective = sources.values().copied().reduce(strictest).unwrap_or(self.default);
+        if sources.is_empty() {
+            overrides.remove(name);
+        }
+
+        let host = self.host(name);
+        if host.limits() != effective {
+            info!("Limits for {}: {:?}", name, effective);
+            host.resize(effective);
+        }
+    }
+
+    /// Wait for an in-flight slot and a token for the host of url
+    pub async fn acquire(&self, url: &str) -> HostPermit {
+        let Some(name) = host_name(url) else {
+            return HostPermit { permit: None };
+        };
+        let host = self.host(&name);
+        let permit = host.in_flight.clone().acquire_owned().await.expect("host semaphore closed");
+        while let Some(wait) = host.take_token(Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+        HostPermit { permit: Some((permit, host)) }
+    }
+}
+
+/// Set up the shared limiter; called at startup
+pub(crate) fn init(default: HostLimits) {
+    if LIMITER.set(HostLimiter::new(default)).is_err() {
+        info!("Host limiter already initialized");
+    }
+}
+
+/// The shared limiter, with the default limits unless `init` was called first
+fn limiter() -> &'static HostLimiter {
+    LIMITER.get_or_init(|| HostLimiter::new(HostLimits::default()))
+}
+
+/// Wait until a request to url is allowed; hold the permit until the response is read
+pub(crate) async fn acquire(url: &str) -> HostPermit {
+    limiter().acquire(url).await
+}
+
+/// Apply a source's own limits to the hosts of its site and of url, or drop them once the source has none
+pub(crate) fn apply_source_limits(source: &Source, url: &str) {
+    let limiter = limiter();
+    let limits = (source.requests_per_second.is_some() || source.max_in_flight.is_some()).then(|| HostLimits {
+        requests_per_second: source.requests_per_second.unwrap_or(limiter.default.requests_per_second),
+        max_in_flight: source.max_in_flight.map_or(limiter.default.max_in_flight, |m| m.max(1) as usize),
+        ..limiter.default
+    });
+    for name in [host_name(&source.url), host_name(url)].into_iter().flatten() {
+        limiter.set_source_limits(&name, source.id, limits);
+    }
+}
+
+/// host[:port] of a url
+fn host_name(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    Some(match url.port() {
+        Some(port) => format!("{}:{}", host, port),
+        None => host.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_token() {
+        let host = Host::new(HostLimits { requests_per_second: 2.0, burst: 2, max_in_flight: 1 });
+        let start = Instant::now();
+        assert_eq!(host.take_token(start), None);
+        assert_eq!(host.take_token(start), None);
+        assert_eq!(host.take_token(start), Some(Duration::from_millis(500)));
+        // half a second refills one token
+        assert_eq!(host.take_token(start + Duration::from_millis(500)), None);
+        // the bucket never holds more than the burst
+        let later = start + Duration::from_secs(60);
+        assert_eq!(host.take_token(later), None);
+        assert_eq!(host.take_token(later), None);
+        assert!(host.take_token(later).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_max_in_flight() {
+        let limiter = HostLimiter::new(HostLimits { requests_per_second: 0.0, burst: 1, max_in_flight: 1 });
+        let first = limiter.acquire("https://example.org/a").await;
+        let blocked = tokio::time::timeout(Duration::from_millis(50), limiter.acquire("https://example.org/b")).await;
+        assert!(blocked.is_err(), "second request to the host should wait");
+        // other hosts aren't affected
+        limiter.acquire("https://example.com/a").await;
+
+        drop(first);
+        let unblocked = tokio::time::timeout(Duration::from_millis(50), limiter.acquire("https://example.org/b")).await;
+        assert!(unblocked.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_source_limits() {
+        let default = HostLimits { requests_per_second: 0.0, burst: 1, max_in_flight: 2 };
+        let limiter = HostLimiter::new(default);
+        let (strict, loose) = (uuid::Uuid::new_v4(), uuid::Uuid::new_v4());
+        let cap = |n| HostLimits { max_in_flight: n, ..default };
+        let first = limiter.acquire("https://example.org/a").await;
+
+        // the strictest source wins whatever the order, and the request in flight still counts
+        limiter.set_source_limits("example.org", strict, Some(cap(1)));
+        limiter.set_source_limits("example.org", loose, Some(cap(4)));
+        assert_eq!(limiter.host("example.org").limits(), cap(1));
+        let blocked = tokio::time::timeout(Duration::from_millis(50), limiter.acquire("https://example.org/b")).await;
+        assert!(blocked.is_err(), "the lowered cap should count the request in flight");
+        drop(first);
+        let second = limiter.acquire("https://example.org/b").await;
+
+        // dropping the overrides restores the defaults
+        limiter.set_source_limits("example.org", strict, None);
+        assert_eq!(limiter.host("example.org").limits(), cap(4));
+        limiter.set_source_limits("example.org", loose, None);
+        assert_eq!(limiter.host("example.org").limits(), default);
+        let third = tokio::time::timeout(Duration::from_millis(50), limiter.acquire("https://example.org/c")).await;
+        assert!(third.is_ok());
+        let fourth = tokio::time::timeout(Duration::from_millis(50), limiter.acquire("https://example.org/d")).await;
+        assert!(fourth.is_err(), "the default cap is 2");
+        drop(second);
+    }
+
+    #[test]
+    fn test_host_name() {
+        assert_eq!(host_name("https://Example.org/feed.xml").as_deref(), Some("example.org"));
+        assert_eq!(host_name("http://127.0.0.1:8080/").as_deref(), Some("127.0.0.1:8080"));
+        assert_eq!(host_name("not a url"), None);
+    }
+}
@@ -1,83 +1,170 @@
 use std::env;
 use std::fs::{self, File};
 use std::io::{Error, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use atom_syndication::{Entry, Feed, Link};
-use chrono::{DateTime, NaiveDate, Utc};
+use anyhow::Context;
+use chrono::Utc;
 use log::{error, info, LevelFilter, warn};
-use opml::Outline;
 use playwright::Playwright;
-use rss::{Channel, Item};
 use serde::Serialize;
-use simplelog::{ColorChoice, CombinedLogger, Config, TerminalMode, TermLogger, WriteLogger};
-use sqlx::{Pool, Postgres};
+use simplelog::{ColorChoice, CombinedLogger, Config, SharedLogger, TerminalMode, TermLogger, WriteLogger};
 use url::Url;
-use webpage::{Webpage, WebpageOptions};
+use webpage::HTML;
 
+use crate::config::{DatabaseConfig, HealthConfig, HostLimits, HttpConfig, ImportConfig, RobotsConfig};
 use crate::extract::extract_text_from_str;
-use crate::models::Source;
-
+use crate::fetch::{FeedFetch, FeedResponse, PageResponse};
+use crate::memory::MemoryStorage;
+use crate::parser::{EnclosureKind, ParsedEntry, ParsedFeed, parser_for};
+use crate::sniff::{FeedFormat, sniff_format};
+use crate::storage::Storage;
+use crate::models::{FEED_RETIRED, Feed, FetchAttempt, FetchError, SaveCounts, Source};
+
+mod config;
+#[cfg(feature = "postgres")]
 mod db;
 mod models;
+mod export;
 mod extract;
+mod fetch;
+mod health;
+mod import;
+mod labels;
+mod jsonfeed;
+mod limiter;
+mod memory;
+mod parser;
+mod rdf;
+mod report;
+mod robots;
+mod scheduler;
+mod sniff;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod storage;
+
+/// Default cap on downloaded enclosures (`ENCLOSURE_MAX_BYTES`)
+const DEFAULT_ENCLOSURE_MAX_BYTES: u64 = 200 * 1024 * 1024;
+
+/// `--dry-run`: nothing is written to disk and only feeds (and the page given) are fetched
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+fn dry_run() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Print what a dry run would have done instead
+fn print_dry_run(action: &str, what: &str, detail: &str) {
+    println!("[dry-run] {} {}: {}", action, what, detail);
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Error> {
     let args: Vec<String> = env::args().collect();
     info!("{:?}", args);
+    // --dry-run: keep everything in memory and print what would be saved
+    DRY_RUN.store(args.iter().any(|a| a == "--dry-run"), Ordering::Relaxed);
+    let args: Vec<String> = args.into_iter().filter(|a| a != "--dry-run").collect();
     if args.len() != 2 {
-        info!("Usage: {} <url>", args[0]);
+        info!("Usage: {} [--dry-run] <url>", args[0]);
         return Ok(());
     }
 
-    CombinedLogger::init(
-        vec![
-            TermLogger::new(LevelFilter::Warn, Config::default(), TerminalMode::Mixed, ColorChoice::Auto),
-            WriteLogger::new(LevelFilter::Info, Config::default(), File::create("feed-fetcher.log").unwrap()),
-        ]
-    ).unwrap();
+    let mut loggers: Vec<Box<dyn SharedLogger>> = vec![
+        TermLogger::new(LevelFilter::Warn, Config::default(), TerminalMode::Mixed, ColorChoice::Auto),
+    ];
+    if !dry_run() {
+        loggers.push(WriteLogger::new(LevelFilter::Info, Config::default(), File::create("feed-fetcher.log").unwrap()));
+    }
+    CombinedLogger::init(loggers).unwrap();
 
     let url = &args[1];
 
-    let dir_path = create_timestamped_dir(url).await;
+    if url == "migrate!" {
+        info!("Running database migrations");
+        let db_config = DatabaseConfig::from_env().expect("Invalid database config");
+        storage::migrate(&db_config).await.expect("Migration failed");
+        return Ok(());
+    }
+
+    let http_config = HttpConfig::from_env().expect("Invalid HTTP config");
+    let health_config = HealthConfig::from_env().expect("Invalid feed health config");
+    fetch::init(&http_config).expect("Unable to build the HTTP client");
+    limiter::init(HostLimits::from_env().expect("Invalid host limits"));
+    robots::init(RobotsConfig::from_env(&http_config).expect("Invalid robots.txt config"));
+
+    let database: Box<dyn Storage> = if dry_run() {
+        Box::new(MemoryStorage::dry_run())
+    } else {
+        let db_config = DatabaseConfig::from_env().expect("Invalid database config");
+        storage::connect(&db_config).await.expect("Failed to connect to the database")
+    };
+    let db = database.as_ref();
+
+    if url.starts_with("report!") {
+        // report!<days>, the last week by default
+        let days = url.replace("report!", "").parse().unwrap_or(report::DEFAULT_REPORT_DAYS);
+        report::run(db, days).await.expect("Error building report");
+        return Ok(());
+    }
+    if url == "dead!" {
+        health::list_dead(db).await.expect("Error listing dead feeds");
+        return Ok(());
+    }
+    if url.starts_with("export!") || url.starts_with("export-by-type!") {
+        // export!<path>, or export-by-type!<path>; stdout without a path, only one label's feeds with #<label>
+        let (group_by, target) = match url.strip_prefix("export!") {
+            Some(target) => (export::GroupBy::Label, target),
+            None => (export::GroupBy::SourceType, url.trim_start_matches("export-by-type!")),
+        };
+        let (path, label) = match target.split_once('#') {
+            Some((path, label)) => (path, Some(label)),
+            None => (target, None),
+        };
+        export::run(db, path, group_by, label).await.expect("Error exporting OPML");
+        return Ok(());
+    }
+    if url.starts_with("labels!") {
+        // labels!, or labels!<label> for what carries it
+        labels::run(db, &url.replace("labels!", "")).await.expect("Error listing labels");
+        return Ok(());
+    }
+    if url.starts_with("reactivate!") {
+        // reactivate!<feed url>, or reactivate!all
+        health::reactivate(db, &url.replace("reactivate!", "")).await.expect("Error reactivating feeds");
+        return Ok(());
+    }
 
-    let pool = db::get_pool().await;
+    let dir_path = create_timestamped_dir(url).await;
 
     if url.starts_with("http") {
         info!("Handling url: {}", url);
         // let dir = dir_path.clone();
-        handle_url(&dir_path, url, &pool).await.expect("Error handling url");
+        handle_url(&dir_path, url, db, &health_config).await.expect("Error handling url");
     } else if url.starts_with("feed!") {
         info!("Handling Feed url: {}", url);
         let orig_feed_url = url.replace("feed!", "");
         let feed_url = get_feed_url(&url, orig_feed_url).await;
-        // TODO create a new source record
-        let source_id = uuid::Uuid::try_from("5f4c7adf-2236-428b-9db6-7fbab59b4507").unwrap();
-        handle_feed(source_id, &feed_url, &dir_path, &pool).await.expect("Feed error");
+        let source = feed_to_source(&feed_url).expect("Invalid feed url");
+        let source_id = source.save(db).await.expect("Error saving source");
+        handle_feed(source_id, &feed_url, &dir_path, db, &health_config).await.expect("Feed error");
     } else if url.starts_with("opml!") {
         info!("Handling OPML url: {}", url);
 
-        // strip opml! from url
+        // strip opml! from url; a path or an http(s) url
         let opml_url = url.replace("opml!", "");
 
-        // determine if url is local or remote
-        if opml_url.starts_with("http") {} else {
-            // assume local file
-            let opml_file = fs::read_to_string(opml_url).expect("Unable to read file");
-            let opml = opml::OPML::from_str(&opml_file).expect("Unable to parse OPML");
-
-            // first, get all the rss outlines from opml
-            let mut outlines = Vec::new();
-            for outline in opml.body.outlines {
-                collect_outlines(&outline, &mut outlines);
-            }
+        // first, get all the rss outlines from the opml and the documents it includes
+        let outlines = import::load_outlines(&opml_url).await.expect("Unable to load OPML");
 
-            // then, handle each outline
-            for outline in outlines {
-                let dir = dir_path.clone();
-                handle_opml_outline(&dir, &outline, &pool).await;
-            }
-        }
+        // then, import them a few at a time
+        let config = ImportConfig::from_env().expect("Invalid import config");
+        let results = import::import_outlines(&dir_path, outlines, config.workers, db, &health_config).await;
+        import::print_summary(&results);
+    } else if url == "daemon!" {
+        info!("Running as daemon");
+        scheduler::run(&dir_path, db, &health_config).await;
     } else {
         error!("Unknown url type: {}", url);
     }
@@ -85,46 +172,19 @@ async fn main() -> Result<(), Error> {
     Ok(())
 }
 
-fn collect_outlines(outline: &Outline, outlines: &mut Vec<Outline>) {
-    // Add the current outline to the list
-    if outline.r#type.is_some() && outline.r#type.clone().unwrap() == "rss" {
-        outlines.push(outline.clone());
-    }
-
-    // Collect all child outlines recursively
-    for child in &outline.outlines {
-        collect_outlines(child, outlines);
-    }
-}
-
-async fn handle_opml_outline(dir_path: &str, outline: &Outline, pool: &Pool<Postgres>) {
-    info!("processing: {:?}", outline);
-
-    // save source
-    if outline.html_url.is_some() {
-        let html_url = outline.html_url.clone().unwrap();
-        let source = Source::new(outline.text.clone(), html_url, 5);
-        let source_id = source.save(&pool).await.expect(save_error("source", source.url.as_str()).as_str());
-        // save feed
-        if outline.xml_url.is_some() {
-            let feed_url = outline.xml_url.clone().unwrap();
-
-            // create feed slug
-            let url_simplified = feed_url.replace("https://", "").replace("http://", "").replace("www.", "");
-            let feed_slug = slug::slugify(url_simplified);
-
-            // create directory for feed
-            let safe_feed_slug = safe_filename(&feed_slug).await;
-            let feed_dir = format!("{}/{}", dir_path, safe_feed_slug);
-            fs::create_dir_all(&feed_dir).expect("Unable to create directory");
-
-            handle_feed(source_id, &feed_url, &feed_dir, pool).await.expect(save_error("feed", &feed_url).as_str());
-        }
+/// Create a directory for a feed under dir_path, named after the feed URL
+async fn create_feed_dir(dir_path: &str, feed_url: &str) -> String {
+    // create feed slug
+    let url_simplified = feed_url.replace("https://", "").replace("http://", "").replace("www.", "");
+    let feed_slug = slug::slugify(url_simplified);
+
+    // create directory for feed
+    let safe_feed_slug = safe_filename(&feed_slug).await;
+    let feed_dir = format!("{}/{}", dir_path, safe_feed_slug);
+    if !dry_run() {
+        fs::create_dir_all(&feed_dir).expect("Unable to create directory");
     }
-}
-
-fn save_error(thing: &str, id: &str) -> String {
-    format!("Error saving {}: {}", thing, id)
+    feed_dir
 }
 
 async fn create_timestamped_dir(url: &str) -> String {
@@ -133,46 +193,76 @@ async fn create_timestamped_dir(url: &str) -> String {
     let url_simplified = url.replace("https://", "").replace("http://", "").replace("www.", "");
     let slug = slug::slugify(url_simplified);
     let dir_path = format!("downloads/{}_{}", timestamp, slug);
-    fs::create_dir_all(&dir_path).expect("Unable to create directory");
+    if !dry_run() {
+        fs::create_dir_all(&dir_path).expect("Unable to create directory");
+    }
     dir_path
 }
 
-async fn handle_url(dir_path: &str, url: &str, pool: &Pool<Postgres>) -> anyhow::Result<()> {
-    let html_options = WebpageOptions { allow_insecure: true, ..Default::default() };
-    let webpage_result = Webpage::from_url(&url, html_options);
-    let webpage = match webpage_result {
+/// What html-info.json holds: the response and what was parsed from it
+#[derive(Serialize)]
+struct PageInfo<'a> {
+    http: &'a PageResponse,
+    html: &'a HTML,
+}
+
+async fn handle_url(dir_path: &str, url: &str, db: &dyn Storage, health: &HealthConfig) -> anyhow::Result<()> {
+    let mut attempt = FetchAttempt::start(url.to_string());
+    attempt.format = Some(FeedFormat::Html.as_str().to_string());
+    // certificates are verified and the default host limits apply unless the source says otherwise
+    let source = db.source_by_url(url).await.ok().flatten();
+    let verify_tls = !source.as_ref().is_some_and(|s| s.skip_tls_verify);
+    if let Some(source) = &source {
+        limiter::apply_source_limits(source, url);
+    }
+    let page = match fetch::fetch_page(url, verify_tls).await {
         Ok(v) => v,
         Err(e) => {
-            error!("Error fetching html webpage: {}", e);
+            error!("Error fetching html webpage: {}; {}", url, e);
+            attempt.fail(FetchError::Network);
+            record_attempt(attempt, db).await;
             return Ok(());
         }
     };
+    attempt.set_response(page.status, &page.body);
+    if page.status >= 400 {
+        error!("Error fetching html webpage: {}; HTTP {}", url, page.status);
+        attempt.fail(FetchError::Http);
+        record_attempt(attempt, db).await;
+        return Ok(());
+    }
+    record_attempt(attempt, db).await;
+    let html = HTML::from_string(page.body.clone(), Some(page.url.clone()))?;
 
     // save source to db
-    let source = webpage_to_source(&webpage);
-    source.save(pool).await.expect("Error saving source");
+    let source = page_to_source(&page, &html);
+    let source_id = source.save(db).await.expect("Error saving source");
 
     info!("source: {:?}", source);
 
-    let content = &webpage.http.body;
-    write_file(dir_path, "content.html", &content).await?;
-    write_json_file(dir_path, "html-info.json", &webpage).await?;
+    write_file(dir_path, "content.html", &page.body).await?;
+    write_json_file(dir_path, "html-info.json", &PageInfo { http: &page, html: &html }).await?;
 
     // If there's a feed available, write it to a file
-    if webpage.html.feed.is_some() {
-        let orig_feed_url = webpage.html.feed.unwrap();
+    if let Some(orig_feed_url) = html.feed.clone() {
         let feed_url = get_feed_url(&url, orig_feed_url).await;
-        handle_feed(source.id, &feed_url, &dir_path, pool).await.expect("Feed error");
+        handle_feed(source_id, &feed_url, dir_path, db, health).await.expect("Feed error");
     }
 
     Ok(())
 }
 
-fn webpage_to_source(webpage: &Webpage) -> Source {
-    let title = webpage.html.title.clone().unwrap();
-    let url = webpage.http.url.clone();
-    let type_id = 5; // Website
-    Source::new(title, url, type_id)
+fn page_to_source(page: &PageResponse, html: &HTML) -> Source {
+    let title = html.title.clone().unwrap_or(page.url.clone());
+    Source::new(title, page.url.clone(), models::WEBSITE_SOURCE_TYPE_ID)
+}
+
+/// The source of a feed given on its own: its site, the origin of the feed url
+fn feed_to_source(feed_url: &str) -> anyhow::Result<Source> {
+    let url = Url::parse(feed_url)?;
+    let origin = url.origin().ascii_serialization();
+    let name = url.host_str().unwrap_or(&origin).to_string();
+    Ok(Source::new(name, format!("{}/", origin), models::WEBSITE_SOURCE_TYPE_ID))
 }
 
 async fn get_feed_url(url: &str, orig_feed_url: String) -> String {
@@ -199,6 +289,10 @@ async fn get_feed_url(url: &str, orig_feed_url: String) -> String {
 async fn write_file(dir_path: &str, file_name: &str, content: &String) -> Result<String, Error>
 {
     let content_path = format!("{}/{}", dir_path, file_name);
+    if dry_run() {
+        print_dry_run("write", "file", &format!("{} ({} bytes)", content_path, content.len()));
+        return Ok(content_path);
+    }
     let mut file: fs::File = fs::File::create(&content_path)?;
     file.write_all(content.as_bytes())?;
     Ok(content_path)
@@ -208,308 +302,363 @@ async fn write_json_file<T>(dir_path: &str, file_name: &str, content: &T) -> Res
     where T: ?Sized + Serialize
 {
     let info_path = format!("{}/{}", dir_path, file_name);
-    let mut file: fs::File = fs::File::create(&info_path)?;
     let info_json = serde_json::to_string_pretty(&content)?;
+    if dry_run() {
+        print_dry_run("write", "file", &format!("{} ({} bytes)", info_path, info_json.len()));
+        return Ok(info_path);
+    }
+    let mut file: fs::File = fs::File::create(&info_path)?;
     file.write_all(&info_json.as_bytes())?;
     Ok(info_path)
 }
 
-async fn handle_feed(source_id: uuid::Uuid, feed_url: &str, dir_path: &str, pool: &Pool<Postgres>) -> Result<(), Error> {
-    let feed_options = WebpageOptions { allow_insecure: true, ..Default::default() };
-    let feed_webpage_result = Webpage::from_url(&feed_url, feed_options);
-    let feed_webpage = match feed_webpage_result {
-        Ok(v) => v,
+/// Fetch, parse and save a feed, recording the attempt; returns how many of its items were new, updated or unchanged
+async fn handle_feed(source_id: uuid::Uuid, feed_url: &str, dir_path: &str, db: &dyn Storage, health: &HealthConfig) -> Result<SaveCounts, Error> {
+    let (result, _) = poll_and_record(source_id, feed_url, dir_path, db, health).await;
+    result
+}
+
+/// `handle_feed`, also returning the attempt's error class when the fetch itself failed
+async fn poll_and_record(source_id: uuid::Uuid, feed_url: &str, dir_path: &str, db: &dyn Storage, health: &HealthConfig) -> (Result<SaveCounts, Error>, Option<String>) {
+    let mut attempt = FetchAttempt::start(feed_url.to_string());
+    let result = poll_feed(source_id, feed_url, dir_path, db, &mut attempt).await;
+    if result.is_err() && attempt.is_success() {
+        attempt.fail(FetchError::Io);
+    }
+    update_health(&attempt, db, health).await;
+    let error_class = attempt.error_class.clone();
+    record_attempt(attempt, db).await;
+    (result, error_class)
+}
+
+/// Count the poll towards the feed's failure streak and backoff, marking it dead when it's hopeless
+async fn update_health(attempt: &FetchAttempt, db: &dyn Storage, config: &HealthConfig) {
+    let Some(feed_id) = attempt.feed_id else {
+        return;
+    };
+    let mut feed = match db.feed_by_id(feed_id).await {
+        Ok(Some(f)) => f,
+        Ok(None) => return,
         Err(e) => {
-            error!("Error fetching feed webpage: {}; {}", feed_url, e);
-            return Ok(());
+            error!("Unable to look up feed: {}; {}", attempt.url, e);
+            return;
         }
     };
+    let ttl = feed.ttl.unwrap_or_else(scheduler::default_ttl);
+    if health::record_poll(&mut feed, attempt, config, ttl, Utc::now()) {
+        warn!("Marking feed dead: {} ({})", feed.url, feed.status_reason.as_deref().unwrap_or_default());
+    }
+    if let Err(e) = db.save_feed_health(&feed).await {
+        error!("Error saving feed health: {}; {}", feed.url, e);
+    }
+}
 
-    // Write the feed body to a file
-    let feed_content = &feed_webpage.http.body;
+/// Save a fetch attempt; failing to do so shouldn't fail the fetch
+async fn record_attempt(attempt: FetchAttempt, db: &dyn Storage) {
+    let url = attempt.url.clone();
+    if let Err(e) = attempt.finish(db).await {
+        error!("Error saving fetch attempt: {}; {}", url, e);
+    }
+}
 
-    write_file(dir_path, "feed.txt", &feed_content).await?;
+async fn poll_feed(source_id: uuid::Uuid, feed_url: &str, dir_path: &str, db: &dyn Storage, attempt: &mut FetchAttempt) -> Result<SaveCounts, Error> {
+    // use the validators from the previous fetch (if any) to make a conditional request
+    let cached_feed = match db.feed_by_url(feed_url).await {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("Unable to look up feed: {}; {}", feed_url, e);
+            None
+        }
+    };
+    attempt.feed_id = cached_feed.as_ref().map(|f| f.id);
+    let etag = cached_feed.as_ref().and_then(|f| f.etag.clone());
+    let last_modified = cached_feed.as_ref().and_then(|f| f.last_modified.clone());
+    // certificates are verified and the default host limits apply unless the source says otherwise
+    let source = db.source_by_id(source_id).await.ok().flatten();
+    let verify_tls = !source.as_ref().is_some_and(|s| s.skip_tls_verify);
+    if let Some(source) = &source {
+        limiter::apply_source_limits(source, feed_url);
+    }
 
-    // Write the feed info to a file
-    write_json_file(dir_path, "feed-info.json", &feed_webpage).await?;
-
-    let rss_parse_result = handle_rss_feed(dir_path, feed_content.to_string(), true).await;
-    if rss_parse_result.is_err() {
-        info!("Trying to parse as Atom feed...");
-        let atom_parse_result = handle_atom_feed(dir_path, feed_content, true).await;
-        if atom_parse_result.is_err() {
-            info!("Error parsing Atom feed: {}", atom_parse_result.err().unwrap());
-        } else {
-            info!("Atom feed parsed successfully");
-
-            let atom = atom_parse_result.unwrap();
-            let title = Option::from(atom.title.value);
-            let feed_type = Option::from("Atom".to_string());
-
-            // save feed to db
-            let feed: models::Feed = feed_webpage_to_feed(source_id, title, feed_type, &feed_webpage);
-            feed.save(pool).await.expect("Error saving feed");
-
-            let entries: Vec<Entry> = atom.entries;
-            if entries.len() == 0 {
-                error!("No entries found in Atom feed");
-            } else {
-                for entry in entries {
-                    let news_item = entry_to_news_item(feed.id, &entry);
-                    let result = news_item.save(pool).await;
-                    match result {
-                        Ok(_) => {}
-                        Err(e) => info!("Error saving news item: {}", e)
-                    }
+    let feed_response = match fetch::fetch_feed(feed_url, etag.as_deref(), last_modified.as_deref(), verify_tls).await {
+        Ok(FeedFetch::Fetched(v)) => v,
+        Ok(FeedFetch::NotModified) => {
+            info!("No new items, feed not modified since last fetch: {}", feed_url);
+            attempt.http_status = Some(304);
+            attempt.new_items = Some(0);
+            return Ok(SaveCounts::default());
+        }
+        Ok(FeedFetch::Gone) => {
+            error!("Feed is gone, retiring it: {}", feed_url);
+            attempt.http_status = Some(410);
+            attempt.fail(FetchError::Gone);
+            if let Some(mut feed) = cached_feed {
+                health::set_status(&mut feed, FEED_RETIRED, Some("410 Gone".to_string()), Utc::now());
+                if let Err(e) = db.save_feed_health(&feed).await {
+                    error!("Error retiring feed: {}; {}", feed_url, e);
                 }
             }
+            return Ok(SaveCounts::default());
         }
-    } else {
-        info!("RSS feed parsed successfully");
+        Err(e) => {
+            error!("Error fetching feed: {}; {}", feed_url, e);
+            let status = fetch::error_status(&e);
+            attempt.http_status = status.map(|s| s as i32);
+            attempt.fail(if status.is_some() { FetchError::Http } else { FetchError::Network });
+            return Ok(SaveCounts::default());
+        }
+    };
+    attempt.set_response(feed_response.status, &feed_response.body);
 
-        let channel: Channel = rss_parse_result.unwrap();
-        let title = Option::from(channel.clone().title);
-        let feed_type = Option::from("RSS".to_string());
+    // a permanent redirect moves the feed to its new url; temporary ones leave it where it is
+    let cached_feed = match &feed_response.permanent_url {
+        Some(new_url) => relocate_feed(cached_feed, new_url, db).await,
+        None => cached_feed,
+    };
+    attempt.feed_id = cached_feed.as_ref().map(|f| f.id);
+    let url = match &cached_feed {
+        Some(f) => f.url.clone(),
+        None => feed_response.permanent_url.clone().unwrap_or(feed_url.to_string()),
+    };
 
-        // save feed to db
-        let feed: models::Feed = feed_webpage_to_feed(source_id, title, feed_type, &feed_webpage);
-        let maybe_id = feed.save(pool).await;
+    // Write the feed body to a file
+    let feed_content = &feed_response.body;
 
-        match maybe_id {
-            Ok(id) => info!("Feed saved successfully: {}", id),
-            Err(e) => info!("Feed not saved (possibly duplicate): {}", e)
-        }
+    write_file(dir_path, "feed.txt", &feed_content).await?;
 
-        let items: Vec<Item> = channel.clone().items;
-        if items.len() == 0 {
-            error!("No items found in RSS feed: {:?}", channel);
-        } else {
-            for item in items {
-                let news_item = item_to_news_item(feed.id, &item);
-                let maybe_id = news_item.save(pool).await;
-                match maybe_id {
-                    Ok(id) => info!("News item saved successfully: {}", id),
-                    Err(e) => info!("News item not saved (possibly duplicate): {}", e)
-                }
-            }
+    // Write the feed info to a file
+    write_json_file(dir_path, "feed-info.json", &feed_response).await?;
+
+    let format = sniff_format(feed_response.content_type.as_deref(), feed_content);
+    info!("Detected feed format {:?}: {}", format, feed_url);
+    attempt.format = Some(format.as_str().to_string());
+    let Some(parser) = parser_for(format) else {
+        match format {
+            FeedFormat::Html => error!("Not a feed, got an HTML page: {}", feed_url),
+            _ => error!("Not a feed, unrecognized content: {}", feed_url),
         }
-    }
-
-    Ok(())
-}
-
-/// Convert an RSS item to a NewsItem
-fn item_to_news_item(feed_id: uuid::Uuid, item: &Item) -> models::NewsItem {
-    let title = item.title.clone().or(Some("n/a".to_string())).unwrap();
-    // set guid to either guid or link
-    let guid = match item.guid.clone() {
-        Some(guid) => guid.value,
-        None => item.link.clone().expect("Unable to get link for guid")
+        attempt.fail(FetchError::NotAFeed);
+        return Ok(SaveCounts::default());
     };
-    let url = item.link.clone().expect("Unable to get link");
-    let maybe_pub_date = item.pub_date.clone();
-    let pub_date: DateTime<Utc> = match maybe_pub_date {
-        Some(dt) => {
-            match parse_date(&dt) {
-                Some(dt) => dt,
-                None => {
-                    error!("{}", date_parse_error(&dt));
-                    Utc::now()
-                }
-            }
+
+    let parsed_feed = match parser.parse(feed_content) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("Error parsing {:?} feed: {}; {}", format, feed_url, e);
+            attempt.fail(FetchError::Parse);
+            return Ok(SaveCounts::default());
         }
-        None => Utc::now()
     };
-    models::NewsItem::new(feed_id, guid, title, pub_date, url)
-}
+    info!("{:?} feed parsed successfully", format);
 
-// write a test for the following function
-fn parse_date(dt: &str) -> Option<DateTime<Utc>> {
-    let dt = &dt.replace(" GMT", " +0000");
+    write_json_file(dir_path, "feed-parsed.json", &parsed_feed).await?;
+    save_content(dir_path, &parsed_feed, verify_tls).await?;
 
-    // ex. 'Tue, 1 Jul 2003 10:52:37 +0200'
-    let pr1 = DateTime::parse_from_rfc2822(dt);
-    match pr1 {
-        Ok(dt) => return Some(dt.with_timezone(&Utc)),
-        Err(_) => {}
-    }
+    // save the feed and all its items in one transaction
+    let feed_type = format.feed_type().map(|t| t.to_string());
+    let mut feed: models::Feed = feed_response_to_feed(source_id, url, parsed_feed.title.clone(), feed_type, &feed_response);
+    feed.ttl = parsed_feed.ttl;
 
-    // ex. '1996-12-19T16:39:57-08:00'
-    let pr2 = DateTime::parse_from_rfc3339(dt);
-    match pr2 {
-        Ok(dt) => return Some(dt.with_timezone(&Utc)),
-        Err(_) => {}
+    if parsed_feed.entries.is_empty() {
+        error!("No entries found in feed: {}", feed_url);
     }
-
-    // see: https://docs.rs/chrono/latest/chrono/format/strftime/index.html
-    let datetime_formats = [
-        "%a, %d %b %Y %H:%M:%S GMT",
-        "%a, %d %b %Y %H:%M:%S %z",
-        "%a, %d %b %Y %H:%M:%S %Z",
-        "%a, %d %b %Y %H:%M:%S GMT",
-        "%a, %e %b %Y %H:%M:%S %Z",
-        "%a, %e %b %Y %H:%M:%S GMT",
-    ];
-
-    for format in &datetime_formats {
-        let pr3 = DateTime::parse_from_str(dt, format);
-        match pr3 {
-            Ok(dt) => return Some(dt.with_timezone(&Utc)),
-            Err(_) => continue
-        }
+    let mut entries = Vec::new();
+    let mut items = Vec::new();
+    for entry in &parsed_feed.entries {
+        let Some(news_item) = entry_to_news_item(feed.id, entry) else {
+            error!("No id or link found for entry: {:?}", entry.title);
+            continue;
+        };
+        entries.push(entry);
+        items.push((news_item, entry.categories.clone()));
     }
 
-    let naivedate_formats = [
-        "%a, %d %b %Y",
-        "%a, %e %b %Y",
-        "%Y-%m-%d",
-        "%Y-%M-%d",
-    ];
-
-    for format in &naivedate_formats {
-        let pr4 = NaiveDate::parse_from_str(dt, format);
-        match pr4 {
-            Ok(d) => {
-                let naive_date_time = d.and_hms_opt(0, 0, 0).unwrap();
-                let dt = DateTime::<Utc>::from_utc(naive_date_time, Utc);
-                return Some(dt);
+    let saved = match db.save_feed_items(&feed, &items).await {
+        Ok((feed_id, saved)) => {
+            attempt.feed_id = Some(feed_id);
+            // first seen at a url it had already moved away from
+            if feed.url != feed_url {
+                if let Err(e) = db.save_feed_alias(feed_id, feed_url).await {
+                    error!("Error saving feed alias: {}; {}", feed_url, e);
+                }
             }
-            Err(_) => continue
+            saved
+        }
+        Err(e) => {
+            attempt.fail(FetchError::Storage);
+            return Err(Error::other(format!("Error saving feed: {}; {}", feed_url, e)));
         }
+    };
+    for (entry, (id, _)) in entries.iter().zip(&saved) {
+        save_enclosures(*id, entry, dir_path, verify_tls, db).await;
     }
 
-
-    None
+    let counts = SaveCounts::from_statuses(saved.iter().map(|(_, status)| status));
+    info!("Saved feed {}: {}", feed_url, counts);
+    attempt.new_items = Some(counts.inserted as i32);
+    Ok(counts)
 }
 
+/// Convert a parsed feed entry to a NewsItem; the entry id is the guid, falling back to its link,
+/// then to a hash of link and title. Items with none of these are skipped.
+fn entry_to_news_item(feed_id: uuid::Uuid, entry: &ParsedEntry) -> Option<models::NewsItem> {
+    let url = entry.url().map(|u| u.to_string());
+    let guid = entry.id.clone().or(url.clone()).or_else(|| fallback_guid(entry))?;
+    // an id is only a fallback url when it is one (guids like `urn:uuid:...` aren't)
+    let url = url.or(entry.id.as_deref().and_then(web_url)).unwrap_or_default();
+    let title = entry.title.clone().unwrap_or("n/a".to_string());
+    let pub_date = entry.published.or(entry.updated).unwrap_or_else(Utc::now);
+    let mut news_item = models::NewsItem::new(feed_id, guid, title, pub_date, url);
+    news_item.authors = Some(entry.authors.join(", ")).filter(|a| !a.is_empty());
+    news_item.set_content(entry.summary.clone(), entry.content.clone());
+    news_item.updated_timestamp = entry.updated;
+    Some(news_item)
+}
 
-#[cfg(test)]
-mod tests {
-    use chrono::DateTime;
-
-    use super::*;
-
-    #[test]
-    fn test_another() {
-        let date_str = "Wed, 31 May 2023";
-        let parsed_date = parse_date(date_str);
-        assert!(parsed_date.is_some(), "Unable to parse");
-    }
+/// s when it's an absolute http(s) url
+fn web_url(s: &str) -> Option<String> {
+    Url::parse(s).ok()
+        .filter(|u| u.scheme() == "http" || u.scheme() == "https")
+        .map(|u| u.to_string())
+}
 
-    #[test]
-    fn test_parse_date_valid_no_time() {
-        let date_str = "2023-06-19";
-        let parsed_date = parse_date(date_str);
-        assert!(parsed_date.is_some(), "Expected Some, got None.");
+/// Stable guid for entries without an id: SHA-256 of link and title
+fn fallback_guid(entry: &ParsedEntry) -> Option<String> {
+    let link = entry.url().unwrap_or_default();
+    let title = entry.title.as_deref().unwrap_or_default();
+    if link.is_empty() && title.is_empty() {
+        return None;
     }
-    /*#[test]
-    fn test_parse_date_valid() {
-        let parsed_date = parsed_date.unwrap();
-        let expected_date: DateTime<Utc> = "2023-06-19T00:00:00Z".parse().unwrap();
-        assert_eq!(parsed_date, expected_date, "Dates do not match.");
-    }*/
+    Some(format!("sha256:{}", models::sha256_hex(&format!("{}\n{}", link, title))))
+}
 
-    #[test]
-    fn test_parse_date_invalid() {
-        let date_str = "Not a date";
-        let parsed_date = parse_date(date_str);
-        assert!(parsed_date.is_none(), "Expected None, got Some.");
+/// Follow a permanent redirect: move the stored feed to its new url or, when that url is already
+/// stored as a feed of its own, retire this one in its favor. Returns the feed to save into.
+async fn relocate_feed(cached_feed: Option<Feed>, new_url: &str, db: &dyn Storage) -> Option<Feed> {
+    let mut feed = cached_feed?;
+    if feed.url == new_url {
+        return Some(feed);
     }
-
-    #[test]
-    fn test_parse_date_with_time() {
-        let date_str = "Wed, 01 Jan 2020 12:34:56 GMT";
-        let parsed_date = parse_date(date_str);
-        assert!(parsed_date.is_some(), "Expected Some, got None.");
-
-        let parsed_date = parsed_date.unwrap();
-        let expected_date: DateTime<Utc> = "2020-01-01T12:34:56Z".parse().unwrap();
-        assert_eq!(parsed_date, expected_date, "Dates do not match.");
+    match db.feed_by_url(new_url).await {
+        Ok(Some(existing)) if existing.id != feed.id => {
+            warn!("Feed moved to a url stored as another feed, retiring it: {} -> {}", feed.url, new_url);
+            health::set_status(&mut feed, FEED_RETIRED, Some(format!("moved to {}", new_url)), Utc::now());
+            if let Err(e) = db.save_feed_health(&feed).await {
+                error!("Error retiring feed: {}; {}", feed.url, e);
+            }
+            Some(existing)
+        }
+        _ => {
+            info!("Feed moved permanently: {} -> {}", feed.url, new_url);
+            match db.move_feed(feed.id, new_url).await {
+                Ok(()) => feed.url = new_url.to_string(),
+                Err(e) => error!("Error moving feed: {} -> {}; {}", feed.url, new_url, e),
+            }
+            Some(feed)
+        }
     }
 }
 
-fn date_parse_error(date: &str) -> String {
-    format!("Failed to parse date and time: '{}'", date)
-}
-
-/// Convert an Atom entry to a NewsItem
-fn entry_to_news_item(feed_id: uuid::Uuid, entry: &Entry) -> models::NewsItem {
-    let title = entry.title.clone().value;
-    let guid = entry.id.clone();
-    let url = entry.links[0].href.clone();
-    let published = match entry.published {
-        Some(p) => p,
-        None => entry.updated.clone()
-    };
-    models::NewsItem::new(feed_id, guid, title, DateTime::from(published), url)
+/// The feed to save for a response, stored under url rather than wherever temporary redirects led
+fn feed_response_to_feed(source_id: uuid::Uuid, url: String, title: Option<String>, feed_type: Option<String>, response: &FeedResponse) -> models::Feed {
+    let mut feed = models::Feed::new(source_id, url, title, feed_type);
+    feed.etag = response.etag.clone();
+    feed.last_modified = response.last_modified.clone();
+    feed
 }
 
-fn feed_webpage_to_feed(source_id: uuid::Uuid, title: Option<String>, feed_type: Option<String>, webpage: &Webpage) -> models::Feed {
-    let url = webpage.http.url.clone();
-    models::Feed::new(source_id, url, title, feed_type)
-}
+/// Record an entry's enclosures and, when `DOWNLOAD_ENCLOSURES=true`, download them into the "content" directory
+async fn save_enclosures(news_id: uuid::Uuid, entry: &ParsedEntry, dir_path: &str, verify_tls: bool, db: &dyn Storage) {
+    let download_limit = enclosure_download_limit();
+    for (i, parsed) in entry.enclosures.iter().enumerate() {
+        let mut enclosure = models::Enclosure::new(news_id, parsed.url.clone(), parsed.kind.as_str().to_string());
+        enclosure.mime_type = parsed.mime_type.clone();
+        enclosure.length = parsed.length;
+        enclosure.duration = parsed.duration;
+        let (id, content_path) = match enclosure.save(db).await {
+            Ok(v) => v,
+            Err(e) => {
+                error!("Error saving enclosure: {}; {}", parsed.url, e);
+                continue;
+            }
+        };
+
+        // thumbnails are only recorded, and files already on disk aren't fetched again
+        let Some(max_bytes) = download_limit else {
+            continue;
+        };
+        if parsed.kind == EnclosureKind::MediaThumbnail || content_path.is_some() {
+            continue;
+        }
+        if parsed.length.is_some_and(|length| length as u64 > max_bytes) {
+            info!("Skipping enclosure larger than {} bytes: {}", max_bytes, parsed.url);
+            continue;
+        }
 
-async fn handle_atom_feed(dir_path: &str, feed_content: &str, save_content_files: bool) -> Result<Feed, atom_syndication::Error> {
-    let feed_parsed = Feed::read_from(feed_content.as_bytes());
-    let parsed_file_path = format!("{}/{}", dir_path, "feed-parsed.json");
-    match &feed_parsed {
-        Ok(feed) => {
-            let feed_parsed_json = serde_json::to_string_pretty(&feed).expect("Unable to serialize Atom feed");
-            let mut feed_parsed_file: fs::File = fs::File::create(&parsed_file_path).expect("Unable to create feed parsed file");
-            feed_parsed_file.write_all(&feed_parsed_json.as_bytes()).expect("Unable to write feed parsed file");
-
-            // for each feed entry save the html content to a file
-            if save_content_files {
-                save_atom_content(dir_path, &feed).await.expect("Unable to save Atom content");
+        let path = enclosure_path(dir_path, entry, i, &parsed.url).await;
+        if dry_run() {
+            print_dry_run("download", "enclosure", &format!("{} -> {}", parsed.url, path));
+            continue;
+        }
+        match fetch::download_file(&parsed.url, &path, max_bytes, verify_tls).await {
+            Ok(bytes) => {
+                info!("Downloaded enclosure ({} bytes): {}", bytes, parsed.url);
+                if let Err(e) = db.set_enclosure_content_path(id, &path).await {
+                    error!("Error saving enclosure path: {}; {}", parsed.url, e);
+                }
             }
+            Err(e) => error!("Error downloading enclosure: {}; {}", parsed.url, e),
         }
-        Err(err) => info!("Not a RSS feed: {}", err),
     }
-
-    return feed_parsed;
 }
 
-async fn save_atom_content(dir_path: &str, feed: &Feed) -> Result<(), Error> {
-    // create "content" directory under dir_path
-    let content_dir_path = format!("{}/{}", dir_path, "content");
-    fs::create_dir_all(&content_dir_path).expect("Unable to create content directory");
-
-    let entries: Vec<Entry> = feed.clone().entries;
-    if entries.len() == 0 {
-        error!("No entries found in Atom feed");
-    } else {
-        for entry in entries {
-            let title = entry.title.clone().value;
-            let title_slug = slug::slugify(title);
-            let maybe_content_url = get_atom_content_url(&entry.links).await;
-            download_content(&content_dir_path, &title_slug, maybe_content_url, true).await;
-        }
+/// Max size in bytes of enclosures to download; None unless `DOWNLOAD_ENCLOSURES=true`
+fn enclosure_download_limit() -> Option<u64> {
+    let enabled = env::var("DOWNLOAD_ENCLOSURES").map(|v| v == "true" || v == "1").unwrap_or(false);
+    if !enabled {
+        return None;
     }
+    let max_bytes = env::var("ENCLOSURE_MAX_BYTES").ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_ENCLOSURE_MAX_BYTES);
+    Some(max_bytes)
+}
 
-    Ok(())
+async fn enclosure_path(dir_path: &str, entry: &ParsedEntry, index: usize, url: &str) -> String {
+    let title = entry.title.clone().or(entry.id.clone()).unwrap_or("enclosure".to_string());
+    let title_slug = slug::slugify(title);
+    let safe_title_slug = safe_filename(&title_slug).await;
+    let extension = Url::parse(url).ok()
+        .and_then(|u| u.path().rsplit_once('.').map(|(_, ext)| ext.to_lowercase()))
+        .filter(|ext| !ext.is_empty() && ext.len() <= 5 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("bin".to_string());
+    format!("{}/content/{}-enclosure-{}.{}", dir_path, safe_title_slug, index, extension)
 }
 
-async fn get_atom_content_url(links: &Vec<Link>) -> Option<String> {
-    if links.len() == 1 {
-        return Some(links.first().unwrap().href.clone());
+/// Download the HTML (and extracted text) of each entry into the "content" directory under dir_path
+async fn save_content(dir_path: &str, parsed_feed: &ParsedFeed, verify_tls: bool) -> Result<(), Error> {
+    let content_dir_path = format!("{}/{}", dir_path, "content");
+    if !dry_run() {
+        fs::create_dir_all(&content_dir_path)?;
+    }
+
+    for entry in &parsed_feed.entries {
+        let Some(title) = entry.title.clone().or(entry.id.clone()) else {
+            error!("No title found for entry: {:?}", entry);
+            continue;
+        };
+        let title_slug = slug::slugify(title);
+        let content_url = entry.content_url().and_then(web_url);
+        download_content(&content_dir_path, &title_slug, content_url, true, verify_tls).await;
     }
-    // iterate over entry.links and find the one with mime type "text/html"
-    return links.into_iter().find(|link| {
-        if link.href.ends_with(".html") || link.href.ends_with(".htm") {
-            return true;
-        }
-        let maybe_mime_type = link.mime_type.clone();
-        match maybe_mime_type {
-            Some(mime_type) => mime_type == "text/html",
-            None => false
-        }
-    }).map(|lnk| lnk.clone().href);
+    Ok(())
 }
 
 async fn download_content(content_dir: &String,
                           title_slug: &String,
                           maybe_content_url: Option<String>,
-                          extract_text: bool) {
+                          extract_text: bool,
+                          verify_tls: bool) {
     match maybe_content_url {
         None => {
             error!("No content URL found for item: {}", title_slug.clone());
@@ -521,14 +670,22 @@ async fn download_content(content_dir: &String,
                 error!("Attempted download with invalid URL: {}", url);
                 return;
             }
+            if dry_run() {
+                print_dry_run("download", "content", &format!("{} -> {}/{}.html", url, content_dir, safe_filename(title_slug).await));
+                return;
+            }
+            if !robots::allowed(&url, verify_tls).await {
+                warn!("Skipping download disallowed by robots.txt: {}", url);
+                return;
+            }
 
             let maybe_content = playwright_fetch(&url).await;
             match maybe_content {
-                None => {
-                    error!("Unable to fetch content for item: {}", title_slug.clone());
+                Err(e) => {
+                    error!("Unable to fetch content for item: {}; {}", title_slug.clone(), e);
                     return;
                 }
-                Some(content) => {
+                Ok(content) => {
                     let safe_title_slug = safe_filename(&title_slug).await;
 
                     let content_file_path = format!("{}/{}.html", content_dir, safe_title_slug);
@@ -560,72 +717,25 @@ async fn safe_filename(orig: &String) -> &str {
     }
 }
 
-async fn handle_rss_feed(dir_path: &str, feed_content: String, save_content_files: bool) -> Result<Channel, rss::Error> {
-    let feed_parsed = Channel::read_from(feed_content.as_bytes());
-    let parsed_file_path = format!("{}/{}", dir_path, "feed-parsed.json");
-    match &feed_parsed {
-        Ok(channel) => {
-            let feed_parsed_json = serde_json::to_string_pretty(&channel).expect("Unable to serialize RSS channel");
-            let mut feed_parsed_file: fs::File = fs::File::create(&parsed_file_path).expect("Unable to create feed parsed file");
-            feed_parsed_file.write_all(&feed_parsed_json.as_bytes()).expect("Unable to write feed parsed file");
-
-            if save_content_files {
-                save_rss_content(dir_path, &channel).await.expect("Unable to save RSS content");
-            }
-        }
-        Err(err) => warn!("Error parsing RSS feed: {}", err),
-    }
-
-    return feed_parsed;
-}
-
-async fn save_rss_content(dir_path: &str, channel: &Channel) -> Result<(), Error> {
-    // create "content" directory under dir_path
-    let content_dir_path = format!("{}/{}", dir_path, "content");
-    fs::create_dir_all(&content_dir_path).expect("Unable to create content directory");
-
-    let items: Vec<Item> = channel.clone().items;
-    if items.len() == 0 {
-        error!("No items found in RSS channel: {:?}", channel);
-    } else {
-        for item in items {
-            match item.title {
-                None => {
-                    error!("No title found for item: {:?}", item);
-                    continue;
-                }
-                Some(title) => {
-                    let title_slug = slug::slugify(title.clone());
-                    let maybe_content_url = item.link.clone();
-                    download_content(&content_dir_path, &title_slug, maybe_content_url, true).await;
-                }
-            }
-        }
-    }
-    Ok(())
-}
-
 /// Using playwright, fetch the content of the URL
 #[allow(dead_code)]
-async fn playwright_fetch(url: &str) -> Option<String> {
+async fn playwright_fetch(url: &str) -> anyhow::Result<String> {
     info!("Fetching URL: {}", url);
-    let playwright = Playwright::initialize().await.expect("Unable to initialize playwright");
-    playwright.prepare().expect("Error installing browsers");
+    let playwright = Playwright::initialize().await.context("Unable to initialize playwright")?;
+    playwright.prepare().context("Error installing browsers")?;
     let chromium = playwright.chromium();
-    let browser = chromium.launcher().headless(true).launch().await.expect("Unable to launch browser");
+    let browser = chromium.launcher().headless(true).launch().await.context("Unable to launch browser")?;
     let context = browser.context_builder()
-        .user_agent("Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0.0.0 Safari/537.36")
-        .build().await.expect("Unable to build context");
-    let page = context.new_page().await.expect("Unable to create page");
-    let goto_result = page.goto_builder(url).goto().await;
-    match goto_result {
-        Ok(_) => {}
-        Err(e) => {
-            error!("Error navigating to URL: {}; {}", url, e);
-            return None;
-        }
-    }
-    page.content().await.ok()
+        .user_agent(fetch::user_agent())
+        .build().await.context("Unable to build context")?;
+    let page = context.new_page().await.context("Unable to create page")?;
+    let _permit = limiter::acquire(url).await;
+    let timeout = fetch::download_timeout();
+    let content = tokio::time::timeout(timeout, async {
+        page.goto_builder(url).goto().await.with_context(|| format!("Error navigating to URL: {}", url))?;
+        anyhow::Ok(page.content().await?)
+    }).await;
+    content.unwrap_or_else(|_| Err(anyhow::anyhow!("timed out after {:?}: {}", timeout, url)))
 }
 
 async fn validate_url(url: &str) -> bool {
@@ -651,3 +761,81 @@ async fn validate_url(url: &str) -> bool {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+
+    use crate::parser::parse_date;
+
+    use super::*;
+
+    #[test]
+    fn test_another() {
+        let date_str = "Wed, 31 May 2023";
+        let parsed_date = parse_date(date_str);
+        assert!(parsed_date.is_some(), "Unable to parse");
+    }
+
+    #[test]
+    fn test_parse_date_valid_no_time() {
+        let date_str = "2023-06-19";
+        let parsed_date = parse_date(date_str);
+        assert!(parsed_date.is_some(), "Expected Some, got None.");
+    }
+    /*#[test]
+    fn test_parse_date_valid() {
+        let parsed_date = parsed_date.unwrap();
+        let expected_date: DateTime<Utc> = "2023-06-19T00:00:00Z".parse().unwrap();
+        assert_eq!(parsed_date, expected_date, "Dates do not match.");
+    }*/
+
+    #[test]
+    fn test_parse_date_invalid() {
+        let date_str = "Not a date";
+        let parsed_date = parse_date(date_str);
+        assert!(parsed_date.is_none(), "Expected None, got Some.");
+    }
+
+    #[test]
+    fn test_parse_date_with_time() {
+        let date_str = "Wed, 01 Jan 2020 12:34:56 GMT";
+        let parsed_date = parse_date(date_str);
+        assert!(parsed_date.is_some(), "Expected Some, got None.");
+
+        let parsed_date = parsed_date.unwrap();
+        let expected_date: DateTime<Utc> = "2020-01-01T12:34:56Z".parse().unwrap();
+        assert_eq!(parsed_date, expected_date, "Dates do not match.");
+    }
+
+    #[test]
+    fn test_entry_without_guid_or_link() {
+        let feed_id = uuid::Uuid::new_v4();
+        let entry = ParsedEntry { title: Some("Status update".to_string()), ..Default::default() };
+        let item = entry_to_news_item(feed_id, &entry).expect("Expected a news item");
+        assert!(item.guid.starts_with("sha256:"));
+        assert_eq!(item.url, "");
+        assert_eq!(entry_to_news_item(feed_id, &entry).unwrap().guid, item.guid, "Fallback guid is not stable.");
+
+        let other = ParsedEntry { title: Some("Another update".to_string()), ..Default::default() };
+        assert_ne!(entry_to_news_item(feed_id, &other).unwrap().guid, item.guid);
+        assert!(entry_to_news_item(feed_id, &ParsedEntry::default()).is_none());
+    }
+
+    #[test]
+    fn test_entry_id_as_url() {
+        let feed_id = uuid::Uuid::new_v4();
+        let entry = |id: &str| ParsedEntry { id: Some(id.to_string()), ..Default::default() };
+        assert_eq!(entry_to_news_item(feed_id, &entry("https://example.org/post")).unwrap().url, "https://example.org/post");
+        assert_eq!(entry_to_news_item(feed_id, &entry("urn:uuid:1225c695-cfb8-4ebb-aaaa-80da344efa6a")).unwrap().url, "");
+        assert_eq!(entry_to_news_item(feed_id, &entry("g1")).unwrap().url, "");
+    }
+
+    #[test]
+    fn test_feed_to_source() {
+        let source = feed_to_source("https://blog.example.org:8443/feeds/all.xml?x=1").unwrap();
+        assert_eq!(source.name, "blog.example.org");
+        assert_eq!(source.url, "https://blog.example.org:8443/");
+        assert!(feed_to_source("not a url").is_err());
+    }
+}
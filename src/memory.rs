@@ -0,0 +1,414 @@
+//! In-memory storage for tests and `--dry-run`, with the same upsert and dedupe rules as the SQL backends
+
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::models::{Enclosure, FetchAttempt, Feed, Label, NewsItem, SaveStatus, Source, SourceType, WEBSITE_SOURCE_TYPE_ID};
+use crate::storage::{duplicate_guids, news_change, NewsChange, Storage};
+
+#[derive(Debug, Default)]
+struct State {
+    source_types: Vec<SourceType>,
+    sources: Vec<Source>,
+    feeds: Vec<Feed>,
+    /// (former url, feed id)
+    feed_url_aliases: Vec<(String, uuid::Uuid)>,
+    labels: Vec<Label>,
+    /// (source or feed id, label id)
+    source_labels: Vec<(uuid::Uuid, i32)>,
+    feed_labels: Vec<(uuid::Uuid, i32)>,
+    news: Vec<NewsItem>,
+    /// Previous values of updated news items
+    revisions: Vec<NewsItem>,
+    categories: Vec<String>,
+    /// (news id, index into `categories`)
+    news_categories: Vec<(uuid::Uuid, usize)>,
+    enclosures: Vec<Enclosure>,
+    fetch_attempts: Vec<FetchAttempt>,
+}
+
+#[derive(Debug)]
+pub(crate) struct MemoryStorage {
+    state: Mutex<State>,
+    /// Print every write to stdout
+    verbose: bool,
+}
+
+impl MemoryStorage {
+    pub(crate) fn new() -> Self {
+        let state = State {
+            // seeded like the migrations
+            source_types: vec![SourceType::new(WEBSITE_SOURCE_TYPE_ID, "Website".to_string(), None)],
+            ..Default::default()
+        };
+        Self { state: Mutex::new(state), verbose: false }
+    }
+
+    /// Storage for `--dry-run`: nothing is persisted, every write is printed instead
+    pub(crate) fn dry_run() -> Self {
+        Self { verbose: true, ..Self::new() }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("memory storage lock poisoned")
+    }
+
+    fn print(&self, action: &str, what: &str, detail: &str) {
+        if self.verbose {
+            println!("[dry-run] {} {}: {}", action, what, detail);
+        }
+    }
+
+    fn save_item(&self, state: &mut State, feed_id: uuid::Uuid, ni: &NewsItem) -> (uuid::Uuid, SaveStatus) {
+        let Some(index) = state.news.iter().position(|n| n.feed_id == feed_id && n.guid == ni.guid) else {
+            state.news.push(NewsItem { feed_id, ..ni.clone() });
+            self.print("insert", "news", &format!("{} ({})", ni.title, ni.url));
+            return (ni.id, SaveStatus::Inserted);
+        };
+
+        let existing = state.news[index].clone();
+        match news_change(&existing.title, &existing.url, existing.content_hash.as_deref(), ni) {
+            NewsChange::Unchanged => (existing.id, SaveStatus::Unchanged),
+            NewsChange::BackfillHash => {
+                let stored = &mut state.news[index];
+                stored.summary = ni.summary.clone();
+                stored.content = ni.content.clone();
+                stored.content_hash = ni.content_hash.clone();
+                (existing.id, SaveStatus::Unchanged)
+            }
+            NewsChange::Revise => {
+                let stored = &mut state.news[index];
+                stored.title = ni.title.clone();
+                stored.url = ni.url.clone();
+                stored.authors = ni.authors.clone();
+                stored.summary = ni.summary.clone();
+                stored.content = ni.content.clone();
+                stored.content_hash = ni.content_hash.clone();
+                stored.updated_timestamp = Some(ni.updated_timestamp.unwrap_or_else(Utc::now));
+                state.revisions.push(existing.clone());
+                self.print("update", "news", &format!("{} ({})", ni.title, ni.url));
+                (existing.id, SaveStatus::Updated)
+            }
+        }
+    }
+
+    /// Id of the label named name, created if needed
+    fn save_label(&self, state: &mut State, name: &str) -> i32 {
+        if let Some(label) = state.labels.iter().find(|l| l.name == name) {
+            return label.id;
+        }
+        let id = state.labels.len() as i32 + 1;
+        state.labels.push(Label { id, name: name.to_string() });
+        self.print("insert", "label", name);
+        id
+    }
+
+    #[cfg(test)]
+    fn revision_count(&self) -> usize {
+        self.state().revisions.len()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn source_types(&self) -> anyhow::Result<Vec<SourceType>> {
+        Ok(self.state().source_types.clone())
+    }
+
+    async fn source_type_by_name(&self, name: &str) -> anyhow::Result<Option<SourceType>> {
+        Ok(self.state().source_types.iter().find(|st| st.name == name).cloned())
+    }
+
+    async fn save_source_type(&self, source_type: &SourceType) -> anyhow::Result<i32> {
+        let mut state = self.state();
+        if state.source_types.iter().any(|st| st.id == source_type.id) {
+            anyhow::bail!("Duplicate source_type id: {}", source_type.id);
+        }
+        state.source_types.push(source_type.clone());
+        self.print("insert", "source_type", &source_type.name);
+        Ok(source_type.id)
+    }
+
+    async fn sources(&self) -> anyhow::Result<Vec<Source>> {
+        Ok(self.state().sources.clone())
+    }
+
+    async fn source_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Source>> {
+        Ok(self.state().sources.iter().find(|s| s.id == id).cloned())
+    }
+
+    async fn source_by_url(&self, url: &str) -> anyhow::Result<Option<Source>> {
+        Ok(self.state().sources.iter().find(|s| s.url == url).cloned())
+    }
+
+    async fn save_source(&self, source: &Source) -> anyhow::Result<uuid::Uuid> {
+        let mut state = self.state();
+        if let Some(existing) = state.sources.iter().find(|s| s.url == source.url) {
+            return Ok(existing.id);
+        }
+        state.sources.push(source.clone());
+        self.print("insert", "source", &format!("{} ({})", source.name, source.url));
+        Ok(source.id)
+    }
+
+    async fn feeds(&self) -> anyhow::Result<Vec<Feed>> {
+        Ok(self.state().feeds.clone())
+    }
+
+    async fn feed_by_url(&self, url: &str) -> anyhow::Result<Option<Feed>> {
+        let state = self.state();
+        let alias_of = state.feed_url_aliases.iter().find(|(alias, _)| alias == url).map(|(_, id)| *id);
+        Ok(state.feeds.iter().find(|f| f.url == url)
+            .or_else(|| state.feeds.iter().find(|f| Some(f.id) == alias_of))
+            .cloned())
+    }
+
+    async fn feed_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Feed>> {
+        Ok(self.state().feeds.iter().find(|f| f.id == id).cloned())
+    }
+
+    async fn save_feed(&self, feed: &Feed) -> anyhow::Result<uuid::Uuid> {
+        let mut state = self.state();
+        if let Some(existing) = state.feeds.iter_mut().find(|f| f.url == feed.url) {
+            if feed.title.is_some() {
+                existing.title = feed.title.clone();
+            }
+            existing.feed_type = feed.feed_type.clone();
+            existing.ttl = feed.ttl;
+            existing.etag = feed.etag.clone();
+            existing.last_modified = feed.last_modified.clone();
+            self.print("update", "feed", &feed.url);
+            return Ok(existing.id);
+        }
+        state.feeds.push(feed.clone());
+        self.print("insert", "feed", &feed.url);
+        Ok(feed.id)
+    }
+
+    async fn move_feed(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()> {
+        let mut state = self.state();
+        let Some(index) = state.feeds.iter().position(|f| f.id == id) else {
+            return Ok(());
+        };
+        let old_url = std::mem::replace(&mut state.feeds[index].url, url.to_string());
+        state.feed_url_aliases.retain(|(alias, _)| alias != url && *alias != old_url);
+        state.feed_url_aliases.push((old_url.clone(), id));
+        self.print("update", "feed", &format!("{} -> {}", old_url, url));
+        Ok(())
+    }
+
+    async fn save_feed_alias(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()> {
+        let mut state = self.state();
+        state.feed_url_aliases.retain(|(alias, _)| alias != url);
+        state.feed_url_aliases.push((url.to_string(), id));
+        self.print("insert", "feed_url_alias", url);
+        Ok(())
+    }
+
+    async fn save_feed_health(&self, feed: &Feed) -> anyhow::Result<()> {
+        if let Some(existing) = self.state().feeds.iter_mut().find(|f| f.id == feed.id) {
+            existing.status = feed.status.clone();
+            existing.status_reason = feed.status_reason.clone();
+            existing.status_timestamp = feed.status_timestamp;
+            existing.consecutive_failures = feed.consecutive_failures;
+            existing.last_success_timestamp = feed.last_success_timestamp;
+            existing.last_new_item_timestamp = feed.last_new_item_timestamp;
+            existing.next_poll_timestamp = feed.next_poll_timestamp;
+            self.print("update", "feed", &format!("{} ({}, {} failures)", feed.url, feed.status, feed.consecutive_failures));
+        }
+        Ok(())
+    }
+
+    async fn labels(&self) -> anyhow::Result<Vec<Label>> {
+        let mut labels = self.state().labels.clone();
+        labels.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(labels)
+    }
+
+    async fn save_source_labels(&self, source_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()> {
+        let mut state = self.state();
+        for label in labels {
+            let label_id = self.save_label(&mut state, label);
+            if !state.source_labels.contains(&(source_id, label_id)) {
+                state.source_labels.push((source_id, label_id));
+                self.print("insert", "source_label", label);
+            }
+        }
+        Ok(())
+    }
+
+    async fn save_feed_labels(&self, feed_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()> {
+        let mut state = self.state();
+        for label in labels {
+            let label_id = self.save_label(&mut state, label);
+            if !state.feed_labels.contains(&(feed_id, label_id)) {
+                state.feed_labels.push((feed_id, label_id));
+                self.print("insert", "feed_label", label);
+            }
+        }
+        Ok(())
+    }
+
+    async fn sources_by_label(&self, name: &str) -> anyhow::Result<Vec<Source>> {
+        let state = self.state();
+        let Some(label) = state.labels.iter().find(|l| l.name == name) else {
+            return Ok(Vec::new());
+        };
+        let mut sources: Vec<Source> = state.sources.iter()
+            .filter(|s| state.source_labels.contains(&(s.id, label.id)))
+            .cloned()
+            .collect();
+        sources.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(sources)
+    }
+
+    async fn feeds_by_label(&self, name: &str) -> anyhow::Result<Vec<Feed>> {
+        let state = self.state();
+        let Some(label) = state.labels.iter().find(|l| l.name == name) else {
+            return Ok(Vec::new());
+        };
+        let mut feeds: Vec<Feed> = state.feeds.iter()
+            .filter(|f| state.feed_labels.contains(&(f.id, label.id)))
+            .cloned()
+            .collect();
+        feeds.sort_by(|a, b| a.url.cmp(&b.url));
+        Ok(feeds)
+    }
+
+    async fn news(&self) -> anyhow::Result<Vec<NewsItem>> {
+        Ok(self.state().news.clone())
+    }
+
+    async fn save_news_item(&self, ni: &NewsItem) -> anyhow::Result<(uuid::Uuid, SaveStatus)> {
+        let saved = self.save_item(&mut self.state(), ni.feed_id, ni);
+        Ok(saved)
+    }
+
+    async fn save_feed_items(&self, feed: &Feed, items: &[(NewsItem, Vec<String>)]) -> anyhow::Result<(uuid::Uuid, Vec<(uuid::Uuid, SaveStatus)>)> {
+        let feed_id = self.save_feed(feed).await?;
+        let mut state = self.state();
+        let mut saved: Vec<(uuid::Uuid, SaveStatus)> = Vec::with_capacity(items.len());
+        for ((ni, categories), duplicate_of) in items.iter().zip(duplicate_guids(items)) {
+            if let Some(j) = duplicate_of {
+                saved.push((saved[j].0, SaveStatus::Unchanged));
+                continue;
+            }
+            let (id, status) = self.save_item(&mut state, feed_id, ni);
+            save_categories(&mut state, id, categories);
+            saved.push((id, status));
+        }
+        Ok((feed_id, saved))
+    }
+
+    async fn save_enclosure(&self, enclosure: &Enclosure) -> anyhow::Result<(uuid::Uuid, Option<String>)> {
+        let mut state = self.state();
+        if let Some(existing) = state.enclosures.iter_mut().find(|e| e.news_id == enclosure.news_id && e.url == enclosure.url) {
+            existing.mime_type = enclosure.mime_type.clone();
+            existing.length = enclosure.length;
+            existing.duration = enclosure.duration;
+            return Ok((existing.id, existing.content_path.clone()));
+        }
+        state.enclosures.push(enclosure.clone());
+        self.print("insert", "enclosure", &enclosure.url);
+        Ok((enclosure.id, None))
+    }
+
+    async fn set_enclosure_content_path(&self, id: uuid::Uuid, content_path: &str) -> anyhow::Result<()> {
+        if let Some(enclosure) = self.state().enclosures.iter_mut().find(|e| e.id == id) {
+            enclosure.content_path = Some(content_path.to_string());
+        }
+        Ok(())
+    }
+
+    async fn save_fetch_attempt(&self, attempt: &FetchAttempt) -> anyhow::Result<()> {
+        self.state().fetch_attempts.push(attempt.clone());
+        let outcome = attempt.error_class.as_deref().unwrap_or("ok");
+        self.print("insert", "fetch_attempt", &format!("{} ({})", attempt.url, outcome));
+        Ok(())
+    }
+
+    async fn fetch_attempts_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<FetchAttempt>> {
+        let mut attempts: Vec<FetchAttempt> = self.state().fetch_attempts.iter()
+            .filter(|a| a.started_timestamp >= since)
+            .cloned()
+            .collect();
+        attempts.sort_by_key(|a| a.started_timestamp);
+        Ok(attempts)
+    }
+}
+
+fn save_categories(state: &mut State, news_id: uuid::Uuid, categories: &[String]) {
+    for category in categories {
+        let index = match state.categories.iter().position(|c| c == category) {
+            Some(index) => index,
+            None => {
+                state.categories.push(category.clone());
+                state.categories.len() - 1
+            }
+        };
+        if !state.news_categories.contains(&(news_id, index)) {
+            state.news_categories.push((news_id, index));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::feed_fixture;
+
+    #[tokio::test]
+    async fn test_save_source_and_feed() {
+        let db = MemoryStorage::new();
+        let (source_id, feed_id) = feed_fixture(&db).await;
+        let again = Source::new("Example again".to_string(), "https://example.org".to_string(), WEBSITE_SOURCE_TYPE_ID);
+        assert_eq!(db.save_source(&again).await.unwrap(), source_id);
+
+        let mut feed = Feed::new(source_id, "https://example.org/feed".to_string(), None, None);
+        feed.ttl = Some(30);
+        assert_eq!(db.save_feed(&feed).await.unwrap(), feed_id);
+        assert_eq!(db.feed_by_url("https://example.org/feed").await.unwrap().unwrap().ttl, Some(30));
+        assert_eq!(db.feeds().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_feed_updates_type_and_title() {
+        let db = MemoryStorage::new();
+        let (source_id, feed_id) = feed_fixture(&db).await;
+        let save = |title: Option<&str>, feed_type: &str| {
+            Feed::new(source_id, "https://example.org/feed".to_string(), title.map(|t| t.to_string()), Some(feed_type.to_string()))
+        };
+
+        db.save_feed(&save(Some("Old"), "RSS")).await.unwrap();
+        db.save_feed(&save(None, "Atom")).await.unwrap();
+        let stored = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        assert_eq!((stored.feed_type.as_deref(), stored.title.as_deref()), (Some("Atom"), Some("Old")));
+
+        db.save_feed(&save(Some("New"), "JSON Feed")).await.unwrap();
+        let stored = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        assert_eq!((stored.feed_type.as_deref(), stored.title.as_deref()), (Some("JSON Feed"), Some("New")));
+    }
+
+    #[tokio::test]
+    async fn test_save_news_item() {
+        let db = MemoryStorage::new();
+        let feed_id = uuid::Uuid::new_v4();
+        let mut item = NewsItem::new(feed_id, "1".to_string(), "Title".to_string(), Utc::now(), "https://example.org/1".to_string());
+        let (id, status) = db.save_news_item(&item).await.unwrap();
+        assert_eq!(status, SaveStatus::Inserted);
+
+        item.id = uuid::Uuid::new_v4();
+        assert_eq!(db.save_news_item(&item).await.unwrap(), (id, SaveStatus::Unchanged));
+
+        item.title = "New title".to_string();
+        assert_eq!(db.save_news_item(&item).await.unwrap(), (id, SaveStatus::Updated));
+        assert_eq!(db.revision_count(), 1);
+
+        // same guid in another feed is another item
+        let other = NewsItem::new(uuid::Uuid::new_v4(), "1".to_string(), "Title".to_string(), Utc::now(), "https://example.com/1".to_string());
+        assert_eq!(db.save_news_item(&other).await.unwrap().1, SaveStatus::Inserted);
+        assert_eq!(db.news().await.unwrap().len(), 2);
+    }
+}
@@ -1,6 +1,18 @@
 use chrono::Utc;
-use sqlx::{Pool, Postgres};
-use crate::db;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use crate::sniff::FeedFormat;
+use crate::storage::Storage;
+
+/// `source_type` row for websites, seeded by the migrations
+pub(crate) const WEBSITE_SOURCE_TYPE_ID: i32 = 5;
+
+/// `feed.status` of feeds the daemon polls
+pub(crate) const FEED_ACTIVE: &str = "active";
+/// `feed.status` of feeds that kept failing or stopped publishing; skipped until reactivated
+pub(crate) const FEED_DEAD: &str = "dead";
+/// `feed.status` of feeds whose server answered 410 Gone; never polled again
+pub(crate) const FEED_RETIRED: &str = "retired";
 
 #[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
 pub(crate) struct SourceType {
@@ -19,8 +31,8 @@ impl SourceType {
         }
     }
 
-    pub async fn save(&self) -> anyhow::Result<i32> {
-        db::save_source_type(self).await
+    pub async fn save(&self, db: &dyn Storage) -> anyhow::Result<i32> {
+        db.save_source_type(self).await
     }
 }
 
@@ -37,6 +49,12 @@ pub(crate) struct Source {
     pub state: Option<String>,
     pub city: Option<String>,
     pub create_timestamp: chrono::DateTime<Utc>,
+    /// Fetch this source's pages and feeds without verifying TLS certificates
+    pub skip_tls_verify: bool,
+    /// Overrides `HOST_REQUESTS_PER_SECOND` for this source's hosts
+    pub requests_per_second: Option<f64>,
+    /// Overrides `HOST_MAX_IN_FLIGHT` for this source's hosts
+    pub max_in_flight: Option<i32>,
 }
 
 impl Source {
@@ -53,11 +71,14 @@ impl Source {
             state: None,
             city: None,
             create_timestamp: Utc::now().into(),
+            skip_tls_verify: false,
+            requests_per_second: None,
+            max_in_flight: None,
         }
     }
 
-    pub async fn save(&self, pool: &Pool<Postgres>) -> anyhow::Result<uuid::Uuid> {
-        db::save_source(self, pool).await
+    pub async fn save(&self, db: &dyn Storage) -> anyhow::Result<uuid::Uuid> {
+        db.save_source(self).await
     }
 }
 
@@ -70,6 +91,17 @@ pub(crate) struct Feed {
     pub create_timestamp: chrono::DateTime<Utc>,
     pub feed_type: Option<String>,
     pub ttl: Option<i32>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// `FEED_ACTIVE`, `FEED_DEAD` or `FEED_RETIRED`
+    pub status: String,
+    pub status_reason: Option<String>,
+    pub status_timestamp: Option<chrono::DateTime<Utc>>,
+    pub consecutive_failures: i32,
+    pub last_success_timestamp: Option<chrono::DateTime<Utc>>,
+    pub last_new_item_timestamp: Option<chrono::DateTime<Utc>>,
+    /// Set while backing off after failures
+    pub next_poll_timestamp: Option<chrono::DateTime<Utc>>,
 }
 
 impl Feed {
@@ -82,14 +114,67 @@ impl Feed {
             create_timestamp: Utc::now().into(),
             feed_type,
             ttl: None,
+            etag: None,
+            last_modified: None,
+            status: FEED_ACTIVE.to_string(),
+            status_reason: None,
+            status_timestamp: None,
+            consecutive_failures: 0,
+            last_success_timestamp: None,
+            last_new_item_timestamp: None,
+            next_poll_timestamp: None,
         }
     }
 
-    pub async fn save(&self, pool: &Pool<Postgres>) -> anyhow::Result<uuid::Uuid> {
-        db::save_feed(self, pool).await
+    #[allow(dead_code)]
+    pub async fn save(&self, db: &dyn Storage) -> anyhow::Result<uuid::Uuid> {
+        db.save_feed(self).await
     }
 }
 
+/// A name attached to sources and feeds, such as the folder of an imported OPML outline
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub(crate) struct Label {
+    pub id: i32,
+    pub name: String,
+}
+
+/// What saving a news item did to the stored row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SaveStatus {
+    Inserted,
+    /// The title, url or content changed; the previous values were kept as a revision
+    Updated,
+    Unchanged,
+}
+
+/// Number of new, updated and unchanged items saved for a feed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub(crate) struct SaveCounts {
+    pub inserted: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+}
+
+impl SaveCounts {
+    pub fn from_statuses<'a>(statuses: impl IntoIterator<Item = &'a SaveStatus>) -> Self {
+        let mut counts = Self::default();
+        for status in statuses {
+            match status {
+                SaveStatus::Inserted => counts.inserted += 1,
+                SaveStatus::Updated => counts.updated += 1,
+                SaveStatus::Unchanged => counts.unchanged += 1,
+            }
+        }
+        counts
+    }
+}
+
+impl std::fmt::Display for SaveCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} new, {} updated, {} unchanged", self.inserted, self.updated, self.unchanged)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
 pub(crate) struct NewsItem {
@@ -102,6 +187,14 @@ pub(crate) struct NewsItem {
     pub create_timestamp: chrono::DateTime<Utc>,
     pub raw_content_path: Option<String>,
     pub text_content_path: Option<String>,
+    /// Comma-separated author names
+    pub authors: Option<String>,
+    pub summary: Option<String>,
+    /// Inline content from the feed (`content:encoded`, Atom `<content>`, ...)
+    pub content: Option<String>,
+    pub updated_timestamp: Option<chrono::DateTime<Utc>>,
+    /// SHA-256 of the content (or summary), used to detect edits
+    pub content_hash: Option<String>,
 }
 
 impl NewsItem {
@@ -121,10 +214,161 @@ impl NewsItem {
             create_timestamp: Utc::now().into(),
             raw_content_path: None,
             text_content_path: None,
+            authors: None,
+            summary: None,
+            content: None,
+            updated_timestamp: None,
+            content_hash: None,
         }
     }
 
-    pub async fn save(&self, pool: &Pool<Postgres>) -> anyhow::Result<uuid::Uuid> {
-        db::save_news_item(self, pool).await
+    /// Set the content and summary, and the hash used to detect later edits
+    pub fn set_content(&mut self, summary: Option<String>, content: Option<String>) {
+        self.content_hash = content.as_ref().or(summary.as_ref()).map(|c| sha256_hex(c));
+        self.summary = summary;
+        self.content = content;
     }
-}
\ No newline at end of file
+
+    #[allow(dead_code)]
+    pub async fn save(&self, db: &dyn Storage) -> anyhow::Result<(uuid::Uuid, SaveStatus)> {
+        db.save_news_item(self).await
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub(crate) struct Enclosure {
+    pub id: uuid::Uuid,
+    pub news_id: uuid::Uuid,
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub length: Option<i64>,
+    pub duration: Option<i32>,
+    pub kind: String,
+    pub content_path: Option<String>,
+    pub create_timestamp: chrono::DateTime<Utc>,
+}
+
+impl Enclosure {
+    pub fn new(news_id: uuid::Uuid, url: String, kind: String) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4(),
+            news_id,
+            url,
+            mime_type: None,
+            length: None,
+            duration: None,
+            kind,
+            content_path: None,
+            create_timestamp: Utc::now(),
+        }
+    }
+
+    /// Returns the id of the enclosure row and the path it was previously downloaded to, if any
+    pub async fn save(&self, db: &dyn Storage) -> anyhow::Result<(uuid::Uuid, Option<String>)> {
+        db.save_enclosure(self).await
+    }
+}
+
+/// Why a fetch attempt failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FetchError {
+    /// DNS, connection, TLS or timeout
+    Network,
+    /// The server answered with an error status
+    Http,
+    /// The server answered 410 Gone
+    Gone,
+    /// The response was HTML or unrecognized content
+    NotAFeed,
+    Parse,
+    /// Saving the feed or its items failed
+    Storage,
+    /// Writing downloaded files failed
+    Io,
+}
+
+impl FetchError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FetchError::Network => "network",
+            FetchError::Http => "http",
+            FetchError::Gone => "gone",
+            FetchError::NotAFeed => "not_a_feed",
+            FetchError::Parse => "parse",
+            FetchError::Storage => "storage",
+            FetchError::Io => "io",
+        }
+    }
+}
+
+/// One poll of a feed or page, successful or not
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub(crate) struct FetchAttempt {
+    pub id: uuid::Uuid,
+    /// None until the feed is stored, and for web pages
+    pub feed_id: Option<uuid::Uuid>,
+    pub url: String,
+    pub started_timestamp: chrono::DateTime<Utc>,
+    pub finished_timestamp: chrono::DateTime<Utc>,
+    pub http_status: Option<i32>,
+    pub bytes: Option<i64>,
+    /// SHA-256 of the response body
+    pub content_hash: Option<String>,
+    pub format: Option<String>,
+    pub new_items: Option<i32>,
+    /// None on success, otherwise a `FetchError`
+    pub error_class: Option<String>,
+}
+
+impl FetchAttempt {
+    /// Start timing an attempt to fetch url
+    pub fn start(url: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: uuid::Uuid::new_v4(),
+            feed_id: None,
+            url,
+            started_timestamp: now,
+            finished_timestamp: now,
+            http_status: None,
+            bytes: None,
+            content_hash: None,
+            format: None,
+            new_items: None,
+            error_class: None,
+        }
+    }
+
+    /// Record the status, size and hash of a response body
+    pub fn set_response(&mut self, status: u16, body: &str) {
+        self.http_status = Some(status as i32);
+        self.bytes = Some(body.len() as i64);
+        self.content_hash = Some(sha256_hex(body));
+    }
+
+    pub fn fail(&mut self, error: FetchError) {
+        self.error_class = Some(error.as_str().to_string());
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.error_class.is_none()
+    }
+
+    /// A web page fetch rather than a feed poll; a feed poll is only recorded as html when it
+    /// got a page back, and then it failed as not a feed
+    pub fn is_page_fetch(&self) -> bool {
+        self.format.as_deref() == Some(FeedFormat::Html.as_str())
+            && self.error_class.as_deref() != Some(FetchError::NotAFeed.as_str())
+    }
+
+    /// Stop the clock and save the attempt
+    pub async fn finish(mut self, db: &dyn Storage) -> anyhow::Result<()> {
+        self.finished_timestamp = Utc::now();
+        db.save_fetch_attempt(&self).await
+    }
+}
+
+/// Hex-encoded SHA-256 of s
+pub(crate) fn sha256_hex(s: &str) -> String {
+    hex::encode(Sha256::digest(s.as_bytes()))
+}
@@ -0,0 +1,551 @@
+//! Format-neutral feed model and one `FeedParser` per supported format
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use log::error;
+use rss::Channel;
+use rss::extension::syndication::UpdatePeriod;
+use serde::Serialize;
+
+use crate::sniff::FeedFormat;
+use crate::{jsonfeed, rdf};
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ParsedFeed {
+    pub format: FeedFormat,
+    pub title: Option<String>,
+    pub links: Vec<ParsedLink>,
+    pub description: Option<String>,
+    /// Advertised polling interval in minutes
+    pub ttl: Option<i32>,
+    pub entries: Vec<ParsedEntry>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct ParsedEntry {
+    pub id: Option<String>,
+    pub title: Option<String>,
+    pub links: Vec<ParsedLink>,
+    pub authors: Vec<String>,
+    pub categories: Vec<String>,
+    pub summary: Option<String>,
+    pub content: Option<String>,
+    pub enclosures: Vec<ParsedEnclosure>,
+    pub published: Option<DateTime<Utc>>,
+    pub updated: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ParsedLink {
+    pub href: String,
+    /// Atom link relation; None is equivalent to "alternate"
+    pub rel: Option<String>,
+    pub mime_type: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ParsedEnclosure {
+    pub url: String,
+    pub mime_type: Option<String>,
+    /// Size in bytes
+    pub length: Option<i64>,
+    /// Play time in seconds
+    pub duration: Option<i32>,
+    pub kind: EnclosureKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) enum EnclosureKind {
+    /// RSS `<enclosure>`, Atom `rel="enclosure"` link or JSON Feed attachment
+    Enclosure,
+    /// Media RSS `media:content`
+    MediaContent,
+    /// Media RSS `media:thumbnail`
+    MediaThumbnail,
+}
+
+impl EnclosureKind {
+    /// Value stored in `enclosure.kind`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EnclosureKind::Enclosure => "enclosure",
+            EnclosureKind::MediaContent => "media:content",
+            EnclosureKind::MediaThumbnail => "media:thumbnail",
+        }
+    }
+}
+
+impl ParsedLink {
+    fn alternate(href: String) -> Self {
+        Self { href, rel: None, mime_type: None }
+    }
+
+    fn is_alternate(&self) -> bool {
+        self.rel.as_deref().is_none_or(|rel| rel == "alternate")
+    }
+}
+
+impl ParsedEntry {
+    /// Permalink of the entry: the first alternate link, or any link if there is none
+    pub fn url(&self) -> Option<&str> {
+        self.links.iter()
+            .find(|l| l.is_alternate())
+            .or(self.links.first())
+            .map(|l| l.href.as_str())
+    }
+
+    /// Link to the HTML version of the entry, for downloading its content
+    pub fn content_url(&self) -> Option<&str> {
+        if self.links.len() == 1 {
+            return self.url();
+        }
+        self.links.iter()
+            .find(|link| {
+                link.href.ends_with(".html") || link.href.ends_with(".htm")
+                    || link.mime_type.as_deref() == Some("text/html")
+            })
+            .map(|l| l.href.as_str())
+            .or(self.url())
+    }
+}
+
+pub(crate) trait FeedParser {
+    fn parse(&self, content: &str) -> anyhow::Result<ParsedFeed>;
+}
+
+/// Parser for a sniffed format, None when the content isn't a feed
+pub(crate) fn parser_for(format: FeedFormat) -> Option<Box<dyn FeedParser + Send + Sync>> {
+    match format {
+        FeedFormat::Rss => Some(Box::new(RssParser)),
+        FeedFormat::Atom => Some(Box::new(AtomParser)),
+        FeedFormat::Rdf => Some(Box::new(RdfParser)),
+        FeedFormat::JsonFeed => Some(Box::new(JsonFeedParser)),
+        FeedFormat::Html | FeedFormat::Unknown => None,
+    }
+}
+
+fn parse_entry_date(date: Option<&str>) -> Option<DateTime<Utc>> {
+    let date = date?;
+    let parsed = parse_date(date);
+    if parsed.is_none() {
+        error!("{}", date_parse_error(date));
+    }
+    parsed
+}
+
+// write a test for the following function
+pub(crate) fn parse_date(dt: &str) -> Option<DateTime<Utc>> {
+    let dt = &dt.replace(" GMT", " +0000");
+
+    // ex. 'Tue, 1 Jul 2003 10:52:37 +0200'
+    let pr1 = DateTime::parse_from_rfc2822(dt);
+    match pr1 {
+        Ok(dt) => return Some(dt.with_timezone(&Utc)),
+        Err(_) => {}
+    }
+
+    // ex. '1996-12-19T16:39:57-08:00'
+    let pr2 = DateTime::parse_from_rfc3339(dt);
+    match pr2 {
+        Ok(dt) => return Some(dt.with_timezone(&Utc)),
+        Err(_) => {}
+    }
+
+    // see: https://docs.rs/chrono/latest/chrono/format/strftime/index.html
+    let datetime_formats = [
+        "%a, %d %b %Y %H:%M:%S GMT",
+        "%a, %d %b %Y %H:%M:%S %z",
+        "%a, %d %b %Y %H:%M:%S %Z",
+        "%a, %d %b %Y %H:%M:%S GMT",
+        "%a, %e %b %Y %H:%M:%S %Z",
+        "%a, %e %b %Y %H:%M:%S GMT",
+    ];
+
+    for format in &datetime_formats {
+        let pr3 = DateTime::parse_from_str(dt, format);
+        match pr3 {
+            Ok(dt) => return Some(dt.with_timezone(&Utc)),
+            Err(_) => continue
+        }
+    }
+
+    let naivedate_formats = [
+        "%a, %d %b %Y",
+        "%a, %e %b %Y",
+        "%Y-%m-%d",
+        "%Y-%M-%d",
+    ];
+
+    for format in &naivedate_formats {
+        let pr4 = NaiveDate::parse_from_str(dt, format);
+        match pr4 {
+            Ok(d) => {
+                let naive_date_time = d.and_hms_opt(0, 0, 0).unwrap();
+                let dt = DateTime::<Utc>::from_utc(naive_date_time, Utc);
+                return Some(dt);
+            }
+            Err(_) => continue
+        }
+    }
+
+
+    None
+}
+
+fn date_parse_error(date: &str) -> String {
+    format!("Failed to parse date and time: '{}'", date)
+}
+
+/// Parse `itunes:duration` / `media:content duration` values: seconds, "MM:SS" or "HH:MM:SS"
+fn parse_duration(duration: &str) -> Option<i32> {
+    duration.trim().split(':').try_fold(0, |total: i32, part| {
+        let seconds = part.trim().parse::<f64>().ok()?;
+        total.checked_mul(60).map(|t| t + seconds.round() as i32)
+    })
+}
+
+/// Extension elements are shaped the same in the rss and atom_syndication crates
+trait ExtensionElement: Sized {
+    fn attrs(&self) -> &BTreeMap<String, String>;
+    fn children(&self) -> &BTreeMap<String, Vec<Self>>;
+}
+
+impl ExtensionElement for rss::extension::Extension {
+    fn attrs(&self) -> &BTreeMap<String, String> {
+        &self.attrs
+    }
+
+    fn children(&self) -> &BTreeMap<String, Vec<Self>> {
+        &self.children
+    }
+}
+
+impl ExtensionElement for atom_syndication::extension::Extension {
+    fn attrs(&self) -> &BTreeMap<String, String> {
+        &self.attrs
+    }
+
+    fn children(&self) -> &BTreeMap<String, Vec<Self>> {
+        &self.children
+    }
+}
+
+/// Media RSS `media:content` / `media:thumbnail` elements, either directly on the item or inside `media:group`
+fn media_enclosures<E: ExtensionElement>(extensions: &BTreeMap<String, BTreeMap<String, Vec<E>>>) -> Vec<ParsedEnclosure> {
+    let Some(media) = extensions.get("media") else {
+        return Vec::new();
+    };
+    let mut enclosures = Vec::new();
+    collect_media(media, &mut enclosures);
+    for group in media.get("group").into_iter().flatten() {
+        collect_media(group.children(), &mut enclosures);
+    }
+    enclosures
+}
+
+fn collect_media<E: ExtensionElement>(elements: &BTreeMap<String, Vec<E>>, enclosures: &mut Vec<ParsedEnclosure>) {
+    for (name, kind) in [("content", EnclosureKind::MediaContent), ("thumbnail", EnclosureKind::MediaThumbnail)] {
+        for element in elements.get(name).into_iter().flatten() {
+            let attrs = element.attrs();
+            let Some(url) = attrs.get("url") else {
+                continue;
+            };
+            enclosures.push(ParsedEnclosure {
+                url: url.clone(),
+                mime_type: attrs.get("type").cloned(),
+                length: attrs.get("fileSize").and_then(|l| l.trim().parse().ok()),
+                duration: attrs.get("duration").and_then(|d| parse_duration(d)),
+                kind,
+            });
+        }
+    }
+}
+
+/// Feeds often list the same file as both an enclosure and media:content; keep the first
+fn dedupe_enclosures(mut enclosures: Vec<ParsedEnclosure>) -> Vec<ParsedEnclosure> {
+    let mut seen = std::collections::HashSet::new();
+    enclosures.retain(|e| seen.insert(e.url.clone()));
+    enclosures
+}
+
+fn non_empty(values: Vec<String>) -> Vec<String> {
+    values.into_iter()
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+pub(crate) struct RssParser;
+
+impl FeedParser for RssParser {
+    fn parse(&self, content: &str) -> anyhow::Result<ParsedFeed> {
+        let channel = Channel::read_from(content.as_bytes())?;
+        let entries = channel.items.iter().map(|item| {
+            let dc = item.dublin_core_ext.as_ref();
+            let mut authors: Vec<String> = item.author.iter().cloned().collect();
+            authors.extend(dc.map(|dc| dc.creators.clone()).unwrap_or_default());
+            let mut categories: Vec<String> = item.categories.iter().map(|c| c.name.clone()).collect();
+            categories.extend(dc.map(|dc| dc.subjects.clone()).unwrap_or_default());
+            let published = item.pub_date.as_deref()
+                .or(dc.and_then(|dc| dc.dates.first()).map(|d| d.as_str()));
+            let itunes_duration = item.itunes_ext.as_ref()
+                .and_then(|it| it.duration.as_deref())
+                .and_then(parse_duration);
+            let mut enclosures: Vec<ParsedEnclosure> = item.enclosure.iter().map(|e| ParsedEnclosure {
+                url: e.url.clone(),
+                mime_type: Some(e.mime_type.clone()).filter(|m| !m.is_empty()),
+                length: e.length.trim().parse().ok(),
+                duration: itunes_duration,
+                kind: EnclosureKind::Enclosure,
+            }).collect();
+            enclosures.extend(media_enclosures(&item.extensions));
+
+            ParsedEntry {
+                id: item.guid.as_ref().map(|g| g.value.clone()),
+                title: item.title.clone(),
+                links: item.link.iter().cloned().map(ParsedLink::alternate).collect(),
+                authors: non_empty(authors),
+                categories: non_empty(categories),
+                summary: item.description.clone(),
+                content: item.content.clone(),
+                enclosures: dedupe_enclosures(enclosures),
+                published: parse_entry_date(published),
+                updated: None,
+            }
+        }).collect();
+
+        Ok(ParsedFeed {
+            format: FeedFormat::Rss,
+            title: Some(channel.title.clone()),
+            links: vec![ParsedLink::alternate(channel.link.clone())],
+            description: Some(channel.description.clone()),
+            ttl: channel_ttl(&channel),
+            entries,
+        })
+    }
+}
+
+/// Polling interval in minutes advertised by an RSS channel through `<ttl>` or `sy:updatePeriod` / `sy:updateFrequency`
+fn channel_ttl(channel: &Channel) -> Option<i32> {
+    let ttl = channel.ttl.as_ref().and_then(|t| t.trim().parse::<i32>().ok());
+    if let Some(ttl) = ttl.filter(|t| *t > 0) {
+        return Some(ttl);
+    }
+
+    channel.syndication_ext().map(|sy| {
+        let period_minutes = match sy.period {
+            UpdatePeriod::Hourly => 60,
+            UpdatePeriod::Daily => 60 * 24,
+            UpdatePeriod::Weekly => 60 * 24 * 7,
+            UpdatePeriod::Monthly => 60 * 24 * 30,
+            UpdatePeriod::Yearly => 60 * 24 * 365,
+        };
+        period_minutes / sy.frequency.max(1) as i32
+    })
+}
+
+pub(crate) struct AtomParser;
+
+impl FeedParser for AtomParser {
+    fn parse(&self, content: &str) -> anyhow::Result<ParsedFeed> {
+        let feed = atom_syndication::Feed::read_from(content.as_bytes())?;
+        let entries = feed.entries.iter().map(|entry| {
+            let (enclosures, links): (Vec<_>, Vec<_>) = entry.links.iter().partition(|l| l.rel == "enclosure");
+            ParsedEntry {
+                id: Some(entry.id.clone()),
+                title: Some(entry.title.value.clone()),
+                links: links.into_iter().map(atom_link).collect(),
+                authors: non_empty(entry.authors.iter().map(|a| a.name.clone()).collect()),
+                categories: non_empty(entry.categories.iter()
+                    .map(|c| c.label.clone().unwrap_or(c.term.clone()))
+                    .collect()),
+                summary: entry.summary.as_ref().map(|s| s.value.clone()),
+                content: entry.content.as_ref().and_then(|c| c.value.clone()),
+                enclosures: dedupe_enclosures(enclosures.into_iter().map(|l| ParsedEnclosure {
+                    url: l.href.clone(),
+                    mime_type: l.mime_type.clone(),
+                    length: l.length.as_ref().and_then(|len| len.trim().parse().ok()),
+                    duration: None,
+                    kind: EnclosureKind::Enclosure,
+                }).chain(media_enclosures(&entry.extensions)).collect()),
+                published: entry.published.map(|p| p.with_timezone(&Utc)),
+                updated: Some(entry.updated.with_timezone(&Utc)),
+            }
+        }).collect();
+
+        Ok(ParsedFeed {
+            format: FeedFormat::Atom,
+            title: Some(feed.title.value.clone()),
+            links: feed.links.iter().map(atom_link).collect(),
+            description: feed.subtitle.as_ref().map(|s| s.value.clone()),
+            ttl: None,
+            entries,
+        })
+    }
+}
+
+fn atom_link(link: &atom_syndication::Link) -> ParsedLink {
+    ParsedLink { href: link.href.clone(), rel: Some(link.rel.clone()), mime_type: link.mime_type.clone() }
+}
+
+pub(crate) struct JsonFeedParser;
+
+impl FeedParser for JsonFeedParser {
+    fn parse(&self, content: &str) -> anyhow::Result<ParsedFeed> {
+        let feed = jsonfeed::parse(content)?;
+        let entries = feed.items.iter().map(|item| {
+            let mut links: Vec<ParsedLink> = item.url.iter().cloned().map(ParsedLink::alternate).collect();
+            links.extend(item.external_url.iter().map(|url| ParsedLink {
+                href: url.clone(),
+                rel: Some("related".to_string()),
+                mime_type: None,
+            }));
+            let authors = if item.authors.is_empty() { item.author.iter().collect() } else { item.authors.iter().collect::<Vec<_>>() };
+
+            ParsedEntry {
+                id: Some(item.id.clone()),
+                title: item.title.clone(),
+                links,
+                authors: non_empty(authors.into_iter().filter_map(|a| a.name.clone()).collect()),
+                categories: non_empty(item.tags.clone()),
+                summary: item.summary.clone(),
+                content: item.content_html.clone().or(item.content_text.clone()),
+                enclosures: item.attachments.iter().map(|a| ParsedEnclosure {
+                    url: a.url.clone(),
+                    mime_type: Some(a.mime_type.clone()),
+                    length: a.size_in_bytes,
+                    duration: a.duration_in_seconds.map(|d| d.round() as i32),
+                    kind: EnclosureKind::Enclosure,
+                }).collect(),
+                published: parse_entry_date(item.date_published.as_deref()),
+                updated: parse_entry_date(item.date_modified.as_deref()),
+            }
+        }).collect();
+
+        Ok(ParsedFeed {
+            format: FeedFormat::JsonFeed,
+            title: Some(feed.title.clone()),
+            links: feed.home_page_url.iter().cloned().map(ParsedLink::alternate).collect(),
+            description: feed.description.clone(),
+            ttl: None,
+            entries,
+        })
+    }
+}
+
+pub(crate) struct RdfParser;
+
+impl FeedParser for RdfParser {
+    fn parse(&self, content: &str) -> anyhow::Result<ParsedFeed> {
+        let feed = rdf::parse(content)?;
+        let entries = feed.items.iter().map(|item| {
+            // rdf:about is the item's identity and usually also its permalink
+            let link = item.link.clone().or(item.about.clone());
+            ParsedEntry {
+                id: item.about.clone(),
+                title: item.title.clone(),
+                links: link.into_iter().map(ParsedLink::alternate).collect(),
+                authors: non_empty(item.creator.iter().cloned().collect()),
+                categories: non_empty(item.subjects.clone()),
+                summary: item.description.clone(),
+                content: item.content.clone(),
+                enclosures: Vec::new(),
+                published: parse_entry_date(item.date.as_deref()),
+                updated: None,
+            }
+        }).collect();
+
+        Ok(ParsedFeed {
+            format: FeedFormat::Rdf,
+            title: feed.title.clone(),
+            links: feed.link.iter().cloned().map(ParsedLink::alternate).collect(),
+            description: feed.description.clone(),
+            ttl: None,
+            entries,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_ttl() {
+        let xml = r#"<rss version="2.0"><channel><title>t</title><link>l</link><description>d</description><ttl>15</ttl></channel></rss>"#;
+        let channel = Channel::read_from(xml.as_bytes()).unwrap();
+        assert_eq!(channel_ttl(&channel), Some(15));
+    }
+
+    #[test]
+    fn test_channel_ttl_from_syndication() {
+        let xml = r#"<rss version="2.0" xmlns:sy="http://purl.org/rss/1.0/modules/syndication/"><channel><title>t</title><link>l</link><description>d</description>
+            <sy:updatePeriod>hourly</sy:updatePeriod><sy:updateFrequency>2</sy:updateFrequency></channel></rss>"#;
+        let channel = Channel::read_from(xml.as_bytes()).unwrap();
+        assert_eq!(channel_ttl(&channel), Some(30));
+    }
+
+    #[test]
+    fn test_rss_parser() {
+        let xml = r#"<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/"><channel><title>Blog</title><link>https://example.org/</link><description>d</description>
+            <item><title>Post</title><link>https://example.org/post</link><guid>post-1</guid><dc:creator>Jane</dc:creator>
+            <category>rust</category><pubDate>Wed, 01 Jan 2020 12:34:56 GMT</pubDate>
+            <enclosure url="https://example.org/ep1.mp3" length="1234" type="audio/mpeg"/></item></channel></rss>"#;
+        let feed = RssParser.parse(xml).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("Blog"));
+
+        let entry = &feed.entries[0];
+        assert_eq!(entry.id.as_deref(), Some("post-1"));
+        assert_eq!(entry.url(), Some("https://example.org/post"));
+        assert_eq!(entry.authors, vec!["Jane"]);
+        assert_eq!(entry.categories, vec!["rust"]);
+        assert_eq!(entry.enclosures[0].length, Some(1234));
+        assert_eq!(entry.published, Some("2020-01-01T12:34:56Z".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("1234"), Some(1234));
+        assert_eq!(parse_duration("05:30"), Some(330));
+        assert_eq!(parse_duration("1:02:03"), Some(3723));
+        assert_eq!(parse_duration("n/a"), None);
+    }
+
+    #[test]
+    fn test_rss_parser_media() {
+        let xml = r#"<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+            <channel><title>Podcast</title><link>https://example.org/</link><description>d</description>
+            <item><title>Episode 1</title><link>https://example.org/ep1</link>
+            <enclosure url="https://example.org/ep1.mp3" length="1234" type="audio/mpeg"/>
+            <itunes:duration>01:00:00</itunes:duration>
+            <media:content url="https://example.org/ep1.mp3" type="audio/mpeg"/>
+            <media:group><media:thumbnail url="https://example.org/ep1.jpg"/></media:group>
+            </item></channel></rss>"#;
+        let feed = RssParser.parse(xml).unwrap();
+
+        let enclosures = &feed.entries[0].enclosures;
+        assert_eq!(enclosures.len(), 2);
+        assert_eq!(enclosures[0].kind, EnclosureKind::Enclosure);
+        assert_eq!(enclosures[0].duration, Some(3600));
+        assert_eq!(enclosures[1].kind, EnclosureKind::MediaThumbnail);
+        assert_eq!(enclosures[1].url, "https://example.org/ep1.jpg");
+    }
+
+    #[test]
+    fn test_atom_parser_links() {
+        let xml = r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>Blog</title><id>urn:blog</id><updated>2020-01-01T00:00:00Z</updated>
+            <entry><title>Post</title><id>urn:post</id><updated>2020-01-02T00:00:00Z</updated>
+            <link rel="alternate" href="https://example.org/post"/>
+            <link rel="enclosure" href="https://example.org/talk.mp4" type="video/mp4" length="99"/></entry></feed>"#;
+        let feed = AtomParser.parse(xml).unwrap();
+
+        let entry = &feed.entries[0];
+        assert_eq!(entry.url(), Some("https://example.org/post"));
+        assert_eq!(entry.content_url(), Some("https://example.org/post"));
+        assert_eq!(entry.enclosures.len(), 1);
+        assert_eq!(entry.enclosures[0].mime_type.as_deref(), Some("video/mp4"));
+        assert!(entry.published.is_none());
+        assert!(entry.updated.is_some());
+    }
+}
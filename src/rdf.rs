@@ -0,0 +1,151 @@
+//! RSS 1.0 / RDF Site Summary (`<rdf:RDF>` root), see https://web.resource.org/rss/1.0/spec
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct RdfFeed {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    pub items: Vec<RdfItem>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub(crate) struct RdfItem {
+    /// `rdf:about`, the item's URI
+    pub about: Option<String>,
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    /// `dc:date`
+    pub date: Option<String>,
+    /// `dc:creator`
+    pub creator: Option<String>,
+    /// `dc:subject`
+    pub subjects: Vec<String>,
+    /// `content:encoded`
+    pub content: Option<String>,
+}
+
+/// Parse an RSS 1.0 document. Elements are matched by local name, so unusual namespace prefixes are fine.
+pub(crate) fn parse(content: &str) -> anyhow::Result<RdfFeed> {
+    let mut reader = Reader::from_str(content);
+    reader.trim_text(true);
+
+    let mut feed = RdfFeed::default();
+    let mut found_root = false;
+    // local names of the currently open elements
+    let mut path: Vec<String> = Vec::new();
+    let mut item: Option<RdfItem> = None;
+
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) => {
+                let name = local_name(&e);
+                if !found_root {
+                    if name != "RDF" {
+                        anyhow::bail!("Not an RDF document, root element: {}", name);
+                    }
+                    found_root = true;
+                } else if name == "item" && item.is_none() {
+                    item = Some(RdfItem { about: about(&e, &reader)?, ..Default::default() });
+                }
+                path.push(name);
+            }
+            Event::End(_) => {
+                let closed = path.pop();
+                if closed.as_deref() == Some("item") {
+                    feed.items.extend(item.take());
+                }
+            }
+            Event::Text(t) => set_text(&mut feed, item.as_mut(), &path, t.unescape()?.into_owned()),
+            Event::CData(c) => set_text(&mut feed, item.as_mut(), &path, String::from_utf8_lossy(&c.into_inner()).into_owned()),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !found_root {
+        anyhow::bail!("Empty RDF document");
+    }
+    Ok(feed)
+}
+
+fn local_name(e: &BytesStart) -> String {
+    String::from_utf8_lossy(e.local_name().as_ref()).into_owned()
+}
+
+fn about(e: &BytesStart, reader: &Reader<&[u8]>) -> anyhow::Result<Option<String>> {
+    for attr in e.attributes().with_checks(false).flatten() {
+        if attr.key.local_name().as_ref() == b"about" {
+            return Ok(Some(attr.decode_and_unescape_value(reader)?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+fn set_text(feed: &mut RdfFeed, item: Option<&mut RdfItem>, path: &[String], text: String) {
+    let (Some(element), Some(parent)) = (path.last(), path.len().checked_sub(2).map(|i| &path[i])) else {
+        return;
+    };
+    match item {
+        Some(item) if parent == "item" => match element.as_str() {
+            "title" => item.title = Some(text),
+            "link" => item.link = Some(text),
+            "description" => item.description = Some(text),
+            "date" => item.date = Some(text),
+            "creator" => item.creator = Some(text),
+            "subject" => item.subjects.push(text),
+            "encoded" => item.content = Some(text),
+            _ => {}
+        },
+        None if parent == "channel" => match element.as_str() {
+            "title" => feed.title = Some(text),
+            "link" => feed.link = Some(text),
+            "description" => feed.description = Some(text),
+            _ => {}
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rdf() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns="http://purl.org/rss/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/">
+  <channel rdf:about="http://arxiv.org/">
+    <title>cs.DB updates on arXiv.org</title>
+    <link>http://arxiv.org/</link>
+    <items><rdf:Seq><rdf:li rdf:resource="http://arxiv.org/abs/2306.00001"/></rdf:Seq></items>
+  </channel>
+  <item rdf:about="http://arxiv.org/abs/2306.00001">
+    <title>A Paper &amp; More</title>
+    <link>http://arxiv.org/abs/2306.00001</link>
+    <description><![CDATA[<p>Abstract</p>]]></description>
+    <dc:creator>Jane Doe</dc:creator>
+    <dc:date>2023-06-19T10:00:00Z</dc:date>
+  </item>
+</rdf:RDF>"#;
+        let feed = parse(content).unwrap();
+        assert_eq!(feed.title.as_deref(), Some("cs.DB updates on arXiv.org"));
+        assert_eq!(feed.items.len(), 1);
+
+        let item = &feed.items[0];
+        assert_eq!(item.about.as_deref(), Some("http://arxiv.org/abs/2306.00001"));
+        assert_eq!(item.title.as_deref(), Some("A Paper & More"));
+        assert_eq!(item.description.as_deref(), Some("<p>Abstract</p>"));
+        assert_eq!(item.creator.as_deref(), Some("Jane Doe"));
+        assert_eq!(item.date.as_deref(), Some("2023-06-19T10:00:00Z"));
+    }
+
+    #[test]
+    fn test_parse_rejects_rss2() {
+        assert!(parse(r#"<rss version="2.0"><channel></channel></rss>"#).is_err());
+    }
+}
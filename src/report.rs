@@ -0,0 +1,202 @@
+//! `report!`: which feeds are failing or stale, from the fetch attempts of the last few days
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::{Feed, FEED_ACTIVE, FEED_DEAD, FEED_RETIRED, FetchAttempt};
+use crate::storage::Storage;
+
+/// Days of fetch attempts covered by `report!` unless given (`report!<days>`)
+pub(crate) const DEFAULT_REPORT_DAYS: i64 = 7;
+
+/// Fetch history of one feed over the report window
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct FeedReport {
+    pub url: String,
+    /// `feed.status`; the daemon only polls active feeds
+    pub status: String,
+    pub attempts: usize,
+    pub failures: usize,
+    /// Failed attempts since the last successful one
+    pub consecutive_failures: usize,
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub last_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub last_success: Option<DateTime<Utc>>,
+    /// Last attempt that found new items
+    pub last_new_items: Option<DateTime<Utc>>,
+}
+
+impl FeedReport {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            status: FEED_ACTIVE.to_string(),
+            attempts: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            last_attempt: None,
+            last_status: None,
+            last_error: None,
+            last_success: None,
+            last_new_items: None,
+        }
+    }
+
+    /// dead or retired: no longer polled; failing: the last attempt failed; not polled: no attempt
+    /// in the window; stale: polled, but no new items in the window
+    pub fn health(&self) -> &str {
+        if self.status != FEED_ACTIVE {
+            &self.status
+        } else if self.consecutive_failures > 0 {
+            "failing"
+        } else if self.attempts == 0 {
+            "not polled"
+        } else if self.last_new_items.is_none() {
+            "stale"
+        } else {
+            "ok"
+        }
+    }
+
+    fn rank(&self) -> usize {
+        ["failing", "not polled", "stale", "ok", FEED_DEAD, FEED_RETIRED].iter().position(|h| *h == self.health()).unwrap_or_default()
+    }
+
+    fn add(&mut self, attempt: &FetchAttempt) {
+        self.attempts += 1;
+        self.last_attempt = Some(attempt.started_timestamp);
+        self.last_status = attempt.http_status;
+        self.last_error = attempt.error_class.clone();
+        if attempt.is_success() {
+            self.consecutive_failures = 0;
+            self.last_success = Some(attempt.started_timestamp);
+            if attempt.new_items.unwrap_or_default() > 0 {
+                self.last_new_items = Some(attempt.started_timestamp);
+            }
+        } else {
+            self.failures += 1;
+            self.consecutive_failures += 1;
+        }
+    }
+}
+
+/// One report per stored feed and per other feed url attempted, worst first; page fetches are left out.
+/// `attempts` must be oldest first.
+pub(crate) fn feed_reports(feeds: &[Feed], attempts: &[FetchAttempt]) -> Vec<FeedReport> {
+    let feed_urls: HashMap<uuid::Uuid, &str> = feeds.iter().map(|f| (f.id, f.url.as_str())).collect();
+    let mut reports: HashMap<String, FeedReport> = feeds.iter()
+        .map(|f| (f.url.clone(), FeedReport { status: f.status.clone(), ..FeedReport::new(f.url.clone()) }))
+        .collect();
+
+    for attempt in attempts.iter().filter(|a| !a.is_page_fetch()) {
+        // a redirected feed is stored under its final url
+        let url = attempt.feed_id.and_then(|id| feed_urls.get(&id).copied()).unwrap_or(&attempt.url);
+        reports.entry(url.to_string()).or_insert_with(|| FeedReport::new(url.to_string())).add(attempt);
+    }
+
+    let mut reports: Vec<FeedReport> = reports.into_values().collect();
+    reports.sort_by(|a, b| a.rank().cmp(&b.rank())
+        .then(b.consecutive_failures.cmp(&a.consecutive_failures))
+        .then(a.url.cmp(&b.url)));
+    reports
+}
+
+/// Print the report over the fetch attempts of the last `days` days
+pub(crate) async fn run(db: &dyn Storage, days: i64) -> anyhow::Result<()> {
+    let since = Utc::now() - Duration::days(days);
+    let feeds = db.feeds().await?;
+    let attempts = db.fetch_attempts_since(since).await?;
+    let reports = feed_reports(&feeds, &attempts);
+
+    println!("Fetch attempts since {}", format_timestamp(Some(since)));
+    println!("{:<10}  {:>8}  {:>6}  {:<16}  {:>6}  {:<10}  {:<16}  {:<16}  URL",
+             "HEALTH", "ATTEMPTS", "FAILED", "LAST ATTEMPT", "STATUS", "ERROR", "LAST SUCCESS", "LAST NEW ITEMS");
+    for r in &reports {
+        println!("{:<10}  {:>8}  {:>6}  {:<16}  {:>6}  {:<10}  {:<16}  {:<16}  {}",
+                 r.health(),
+                 r.attempts,
+                 r.failures,
+                 format_timestamp(r.last_attempt),
+                 r.last_status.map_or("-".to_string(), |s| s.to_string()),
+                 r.last_error.as_deref().unwrap_or("-"),
+                 format_timestamp(r.last_success),
+                 format_timestamp(r.last_new_items),
+                 r.url);
+    }
+
+    let count = |health: &str| reports.iter().filter(|r| r.health() == health).count();
+    println!("{} failing, {} not polled, {} stale, {} ok, {} dead, {} retired",
+             count("failing"), count("not polled"), count("stale"), count("ok"), count(FEED_DEAD), count(FEED_RETIRED));
+    Ok(())
+}
+
+/// A timestamp to the minute for report tables, `-` when there is none
+pub(crate) fn format_timestamp(timestamp: Option<DateTime<Utc>>) -> String {
+    timestamp.map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M").to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::FetchError;
+    use crate::sniff::FeedFormat;
+
+    use super::*;
+
+    fn attempt(url: &str, feed_id: Option<uuid::Uuid>, error: Option<FetchError>, new_items: i32) -> FetchAttempt {
+        let mut attempt = FetchAttempt::start(url.to_string());
+        attempt.feed_id = feed_id;
+        attempt.new_items = Some(new_items);
+        if let Some(error) = error {
+            attempt.fail(error);
+        }
+        attempt
+    }
+
+    fn page(url: &str) -> FetchAttempt {
+        let mut attempt = attempt(url, None, None, 0);
+        attempt.format = Some(FeedFormat::Html.as_str().to_string());
+        attempt
+    }
+
+    #[test]
+    fn test_feed_reports() {
+        let ok = Feed::new(uuid::Uuid::new_v4(), "https://example.org/ok.xml".to_string(), None, None);
+        let stale = Feed::new(uuid::Uuid::new_v4(), "https://example.org/stale.xml".to_string(), None, None);
+        let idle = Feed::new(uuid::Uuid::new_v4(), "https://example.org/idle.xml".to_string(), None, None);
+        let mut dead = Feed::new(uuid::Uuid::new_v4(), "https://example.org/dead.xml".to_string(), None, None);
+        dead.status = FEED_DEAD.to_string();
+        let attempts = vec![
+            attempt("https://example.org/ok.xml", Some(ok.id), Some(FetchError::Network), 0),
+            attempt("https://example.org/ok.xml", Some(ok.id), None, 3),
+            // stored under its final url after a redirect
+            attempt("http://example.org/stale", Some(stale.id), None, 0),
+            attempt("https://example.com/new.xml", None, Some(FetchError::Http), 0),
+            attempt("https://example.com/new.xml", None, Some(FetchError::Http), 0),
+            // a page fetched from the stale feed's site, not a poll of the feed
+            page("http://example.org/stale"),
+            // a feed url that turned out to be a page
+            {
+                let mut not_a_feed = attempt("https://example.com/page.xml", None, Some(FetchError::NotAFeed), 0);
+                not_a_feed.format = Some(FeedFormat::Html.as_str().to_string());
+                not_a_feed
+            },
+        ];
+
+        let reports = feed_reports(&[ok.clone(), stale.clone(), idle.clone(), dead], &attempts);
+        let summary: Vec<(&str, &str)> = reports.iter().map(|r| (r.url.as_str(), r.health())).collect();
+        assert_eq!(summary, vec![
+            ("https://example.com/new.xml", "failing"),
+            ("https://example.com/page.xml", "failing"),
+            ("https://example.org/idle.xml", "not polled"),
+            ("https://example.org/stale.xml", "stale"),
+            ("https://example.org/ok.xml", "ok"),
+            ("https://example.org/dead.xml", "dead"),
+        ]);
+        assert_eq!(reports[0].consecutive_failures, 2);
+        assert_eq!(reports[0].last_error.as_deref(), Some("http"));
+        assert_eq!(reports[3].attempts, 1);
+        assert_eq!((reports[4].attempts, reports[4].failures, reports[4].consecutive_failures), (2, 1, 0));
+    }
+}
@@ -0,0 +1,269 @@
+//! robots.txt for article downloads: each host's file is fetched once per `ROBOTS_CACHE_TTL`, and its
+//! `Disallow`/`Allow` rules and `Crawl-delay` for our user agent are honored. Feed urls are exempt.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use url::Url;
+
+use crate::config::RobotsConfig;
+use crate::fetch;
+
+/// How long a host whose robots.txt couldn't be fetched stays off limits before it is tried again
+const UNREACHABLE_TTL: Duration = Duration::from_secs(3600);
+
+static ROBOTS: OnceLock<RobotsCache> = OnceLock::new();
+
+/// The rules of one robots.txt that apply to our user agent
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Robots {
+    rules: Vec<Rule>,
+    crawl_delay: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    /// Everything is disallowed, used while a host's robots.txt can't be fetched
+    fn disallow_all() -> Self {
+        Self { rules: vec![Rule { allow: false, pattern: "/".to_string() }], crawl_delay: None }
+    }
+
+    /// The groups naming agent, or the `*` group when none does
+    pub fn parse(body: &str, agent: &str) -> Self {
+        let mut groups: Vec<(Vec<String>, Robots)> = Vec::new();
+        let mut in_rules = true;
+        for line in body.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            match key.trim().to_lowercase().as_str() {
+                "user-agent" => {
+                    // consecutive User-agent lines share one group
+                    if in_rules {
+                        groups.push((Vec::new(), Robots::default()));
+                        in_rules = false;
+                    }
+                    if let Some((agents, _)) = groups.last_mut() {
+                        agents.push(value.to_lowercase());
+                    }
+                }
+                key @ ("allow" | "disallow") => {
+                    in_rules = true;
+                    if let Some((_, robots)) = groups.last_mut().filter(|_| !value.is_empty()) {
+                        robots.rules.push(Rule { allow: key == "allow", pattern: value.to_string() });
+                    }
+                }
+                "crawl-delay" => {
+                    in_rules = true;
+                    if let (Some((_, robots)), Ok(delay)) = (groups.last_mut(), value.parse::<f64>()) {
+                        robots.crawl_delay = Duration::try_from_secs_f64(delay).ok();
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let agent = agent.to_lowercase();
+        let named = groups.iter().any(|(agents, _)| agents.contains(&agent));
+        groups.into_iter()
+            .filter(|(agents, _)| if named { agents.contains(&agent) } else { agents.iter().any(|a| a == "*") })
+            .fold(Robots::default(), |mut merged, (_, robots)| {
+                merged.rules.extend(robots.rules);
+                merged.crawl_delay = merged.crawl_delay.max(robots.crawl_delay);
+                merged
+            })
+    }
+
+    /// The longest matching rule wins, `Allow` on a tie; paths no rule matches are allowed
+    pub fn is_allowed(&self, url: &Url) -> bool {
+        let mut path = url.path().to_string();
+        if let Some(query) = url.query() {
+            path = format!("{}?{}", path, query);
+        }
+        if path == "/robots.txt" {
+            return true;
+        }
+        self.rules.iter()
+            .filter(|rule| matches(&rule.pattern, &path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .is_none_or(|rule| rule.allow)
+    }
+}
+
+/// robots.txt path pattern: a prefix where `*` matches anything and a trailing `$` anchors the end
+fn matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = match pattern.strip_suffix('$') {
+        Some(p) => (p, true),
+        None => (pattern, false),
+    };
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = path.strip_prefix(parts.next().unwrap_or_default()) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    for (i, part) in parts.iter().enumerate() {
+        if anchored && i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    !anchored || rest.is_empty()
+}
+
+struct Entry {
+    robots: Robots,
+    expires: Instant,
+    /// Earliest time the next download from the host may start, per its `Crawl-delay`
+    next_request: Instant,
+}
+
+/// Parsed robots.txt per origin (`scheme://host:port`)
+pub(crate) struct RobotsCache {
+    config: RobotsConfig,
+    hosts: Mutex<HashMap<String, Entry>>,
+}
+
+impl RobotsCache {
+    pub fn new(config: RobotsConfig) -> Self {
+        Self { config, hosts: Mutex::new(HashMap::new()) }
+    }
+
+    /// Whether url may be downloaded; when it may, waits until the host's `Crawl-delay` has passed
+    pub async fn allowed(&self, url: &str, verify_tls: bool) -> bool {
+        let Ok(url) = Url::parse(url) else {
+            return false;
+        };
+        let origin = url.origin().ascii_serialization();
+        let cached = self.hosts.lock().expect("robots cache lock poisoned")
+            .get(&origin).filter(|e| e.expires > Instant::now()).map(|e| e.robots.clone());
+        let robots = match cached {
+            Some(robots) => robots,
+            None => {
+                let (robots, ttl) = self.fetch(&origin, verify_tls).await;
+                let now = Instant::now();
+                self.hosts.lock().expect("robots cache lock poisoned")
+                    .insert(origin.clone(), Entry { robots: robots.clone(), expires: now + ttl, next_request: now });
+                robots
+            }
+        };
+        if !robots.is_allowed(&url) {
+            return false;
+        }
+
+        if let Some(delay) = robots.crawl_delay {
+            let wait = {
+                let mut hosts = self.hosts.lock().expect("robots cache lock poisoned");
+                let now = Instant::now();
+                match hosts.get_mut(&origin) {
+                    Some(entry) => {
+                        let start = entry.next_request.max(now);
+                        entry.next_request = start + delay;
+                        start - now
+                    }
+                    None => Duration::ZERO,
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+        true
+    }
+
+    /// A missing robots.txt (4xx) allows everything; an unreachable one (5xx, network) disallows everything for a while
+    async fn fetch(&self, origin: &str, verify_tls: bool) -> (Robots, Duration) {
+        let robots_url = format!("{}/robots.txt", origin);
+        match fetch::fetch_page(&robots_url, verify_tls).await {
+            Ok(page) if page.status < 300 => {
+                info!("Fetched {}", robots_url);
+                (Robots::parse(&page.body, &self.config.agent), self.config.cache_ttl)
+            }
+            Ok(page) if page.status < 500 => (Robots::default(), self.config.cache_ttl),
+            Ok(page) => {
+                warn!("Unable to fetch {}: HTTP {}; not downloading from the host for now", robots_url, page.status);
+                (Robots::disallow_all(), self.config.cache_ttl.min(UNREACHABLE_TTL))
+            }
+            Err(e) => {
+                warn!("Unable to fetch {}: {}; not downloading from the host for now", robots_url, e);
+                (Robots::disallow_all(), self.config.cache_ttl.min(UNREACHABLE_TTL))
+            }
+        }
+    }
+}
+
+/// Set up the shared cache; called at startup
+pub(crate) fn init(config: RobotsConfig) {
+    if ROBOTS.set(RobotsCache::new(config)).is_err() {
+        info!("robots.txt cache already initialized");
+    }
+}
+
+/// Whether url may be downloaded, per its host's robots.txt; waits out the host's `Crawl-delay`
+pub(crate) async fn allowed(url: &str, verify_tls: bool) -> bool {
+    ROBOTS.get_or_init(|| RobotsCache::new(RobotsConfig::default())).allowed(url, verify_tls).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS_TXT: &str = "
+# comments are ignored
+User-agent: *
+Disallow: /
+
+User-agent: Feed-Fetcher
+User-agent: otherbot
+Disallow: /private
+Allow: /private/public
+Disallow: /*.pdf$
+Crawl-delay: 2.5
+
+User-agent: feed-fetcher
+Disallow: /drafts/
+";
+
+    fn allowed(robots: &Robots, path: &str) -> bool {
+        robots.is_allowed(&Url::parse("https://example.org").unwrap().join(path).unwrap())
+    }
+
+    #[test]
+    fn test_parse_named_groups() {
+        let robots = Robots::parse(ROBOTS_TXT, "feed-fetcher");
+        assert_eq!(robots.crawl_delay, Some(Duration::from_millis(2500)));
+        assert!(allowed(&robots, "/news/story"));
+        assert!(!allowed(&robots, "/private/notes"));
+        assert!(allowed(&robots, "/private/public/page"));
+        assert!(!allowed(&robots, "/drafts/story"));
+        assert!(!allowed(&robots, "/files/report.pdf"));
+        assert!(allowed(&robots, "/files/report.pdf?page=2"));
+    }
+
+    #[test]
+    fn test_parse_falls_back_to_star() {
+        let robots = Robots::parse(ROBOTS_TXT, "somebot");
+        assert!(!allowed(&robots, "/news/story"));
+        assert!(allowed(&robots, "/robots.txt"));
+        assert!(allowed(&Robots::parse("", "somebot"), "/news/story"));
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("/a", "/abc"));
+        assert!(!matches("/a$", "/abc"));
+        assert!(matches("/*/c", "/a/b/c"));
+        assert!(matches("/*c$", "/a/b/c"));
+        assert!(!matches("/*c$", "/a/b/cd"));
+        assert!(!matches("/b", "/a/b"));
+    }
+}
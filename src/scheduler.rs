@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::env;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use rand::Rng;
+
+use crate::config::HealthConfig;
+use crate::models::FEED_ACTIVE;
+use crate::storage::Storage;
+
+/// Poll interval (minutes) for feeds that don't advertise one
+const DEFAULT_TTL_MINUTES: i32 = 60;
+
+/// Never poll a feed more often than this, whatever its ttl says
+const MIN_TTL_MINUTES: i32 = 5;
+
+/// Fraction of the ttl used as +/- jitter so feeds don't all poll in lockstep
+const JITTER_FRACTION: f64 = 0.1;
+
+/// Upper bound on how long the daemon sleeps, so new feeds in the db get picked up
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Run forever, polling every live feed in the db according to its ttl, or its backoff after failures
+pub(crate) async fn run(dir_path: &str, db: &dyn Storage, health: &HealthConfig) {
+    let default_ttl = default_ttl();
+    info!("Starting feed scheduler (default ttl: {} minutes)", default_ttl);
+
+    let mut next_polls: HashMap<uuid::Uuid, DateTime<Utc>> = HashMap::new();
+
+    loop {
+        let feeds = match db.feeds().await {
+            Ok(f) => f.into_iter().filter(|f| f.status == FEED_ACTIVE).collect::<Vec<_>>(),
+            Err(e) => {
+                error!("Unable to load feeds: {}", e);
+                tokio::time::sleep(MAX_SLEEP).await;
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+        for feed in &feeds {
+            // spread the first poll of newly seen feeds over their ttl, unless they're backing off
+            next_polls.entry(feed.id).or_insert_with(|| {
+                let ttl = feed.ttl.unwrap_or(default_ttl);
+                let spread = now + chrono::Duration::seconds(rand::thread_rng().gen_range(0..=ttl_seconds(ttl)));
+                feed.next_poll_timestamp.map_or(spread, |backoff| backoff.max(spread))
+            });
+        }
+
+        let due: Vec<_> = feeds.iter().filter(|f| next_polls[&f.id] <= now).collect();
+        for feed in due {
+            info!("Polling feed: {}", feed.url);
+            let feed_dir = crate::create_feed_dir(dir_path, &feed.url).await;
+            if let Err(e) = crate::handle_feed(feed.source_id, &feed.url, &feed_dir, db, health).await {
+                error!("Error polling feed: {}; {}", feed.url, e);
+            }
+
+            // the poll may have updated the advertised ttl, started a backoff, or marked the feed dead or retired
+            let polled = match db.feed_by_id(feed.id).await {
+                Ok(Some(f)) => f,
+                _ => feed.clone(),
+            };
+            if polled.status != FEED_ACTIVE {
+                next_polls.remove(&feed.id);
+                continue;
+            }
+            let ttl = polled.ttl.unwrap_or(default_ttl);
+            let next_poll = polled.next_poll_timestamp
+                .unwrap_or_else(|| Utc::now() + next_poll_delay(ttl, rand::thread_rng().gen_range(-1.0..=1.0)));
+            info!("Next poll of {} at {}", feed.url, next_poll);
+            next_polls.insert(feed.id, next_poll);
+        }
+
+        let sleep = next_polls.values().min()
+            .and_then(|next| (*next - Utc::now()).to_std().ok())
+            .map_or(MAX_SLEEP, |d| d.min(MAX_SLEEP));
+        tokio::time::sleep(sleep).await;
+    }
+}
+
+/// Default ttl in minutes, overridable with `DEFAULT_FEED_TTL`
+pub(crate) fn default_ttl() -> i32 {
+    env::var("DEFAULT_FEED_TTL").ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_TTL_MINUTES)
+}
+
+fn ttl_seconds(ttl_minutes: i32) -> i64 {
+    i64::from(ttl_minutes.max(MIN_TTL_MINUTES)) * 60
+}
+
+/// Delay until the next poll; `jitter` in [-1, 1] scales the +/- jitter window
+fn next_poll_delay(ttl_minutes: i32, jitter: f64) -> chrono::Duration {
+    let seconds = ttl_seconds(ttl_minutes) as f64;
+    let jittered = seconds + seconds * JITTER_FRACTION * jitter.clamp(-1.0, 1.0);
+    chrono::Duration::seconds(jittered.round() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_poll_delay_without_jitter() {
+        assert_eq!(next_poll_delay(30, 0.0), chrono::Duration::minutes(30));
+    }
+
+    #[test]
+    fn test_next_poll_delay_jitter_bounds() {
+        assert_eq!(next_poll_delay(60, 1.0), chrono::Duration::minutes(66));
+        assert_eq!(next_poll_delay(60, -1.0), chrono::Duration::minutes(54));
+    }
+
+    #[test]
+    fn test_next_poll_delay_enforces_minimum() {
+        assert_eq!(next_poll_delay(1, 0.0), chrono::Duration::minutes(MIN_TTL_MINUTES as i64));
+    }
+}
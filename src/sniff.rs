@@ -0,0 +1,139 @@
+//! Detect the format of a fetched feed from its root element, falling back to the Content-Type header
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub(crate) enum FeedFormat {
+    Rss,
+    Atom,
+    Rdf,
+    JsonFeed,
+    Html,
+    Unknown,
+}
+
+impl FeedFormat {
+    /// Name recorded with fetch attempts
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedFormat::Rss => "rss",
+            FeedFormat::Atom => "atom",
+            FeedFormat::Rdf => "rdf",
+            FeedFormat::JsonFeed => "json_feed",
+            FeedFormat::Html => "html",
+            FeedFormat::Unknown => "unknown",
+        }
+    }
+
+    /// Value stored in `feed.feed_type`; None for content that isn't a feed
+    pub fn feed_type(&self) -> Option<&'static str> {
+        match self {
+            FeedFormat::Rss => Some("RSS"),
+            FeedFormat::Atom => Some("Atom"),
+            FeedFormat::Rdf => Some("RDF"),
+            FeedFormat::JsonFeed => Some("JSONFeed"),
+            FeedFormat::Html | FeedFormat::Unknown => None,
+        }
+    }
+}
+
+pub(crate) fn sniff_format(content_type: Option<&str>, body: &str) -> FeedFormat {
+    let body = body.trim_start_matches('\u{feff}').trim_start();
+
+    if body.starts_with('{') {
+        return sniff_json(body);
+    }
+
+    if let Some(root) = root_element(body) {
+        let local_name = root.rsplit(':').next().unwrap_or(root);
+        match local_name.to_ascii_lowercase().as_str() {
+            "rss" => return FeedFormat::Rss,
+            "feed" => return FeedFormat::Atom,
+            "rdf" => return FeedFormat::Rdf,
+            "html" => return FeedFormat::Html,
+            _ => {}
+        }
+    }
+
+    // the body wasn't conclusive, trust the server
+    let mime_type = content_type
+        .and_then(|ct| ct.split(';').next())
+        .map(|ct| ct.trim().to_ascii_lowercase());
+    match mime_type.as_deref() {
+        Some("application/rss+xml") => FeedFormat::Rss,
+        Some("application/atom+xml") => FeedFormat::Atom,
+        Some("application/rdf+xml") => FeedFormat::Rdf,
+        Some("application/feed+json") => FeedFormat::JsonFeed,
+        Some("text/html") | Some("application/xhtml+xml") => FeedFormat::Html,
+        _ => FeedFormat::Unknown,
+    }
+}
+
+fn sniff_json(body: &str) -> FeedFormat {
+    let value: serde_json::Value = match serde_json::from_str(body) {
+        Ok(v) => v,
+        Err(_) => return FeedFormat::Unknown,
+    };
+    match value.get("version").and_then(|v| v.as_str()) {
+        Some(version) if version.starts_with("https://jsonfeed.org/version/") => FeedFormat::JsonFeed,
+        _ => FeedFormat::Unknown,
+    }
+}
+
+/// Name of the first element, skipping the XML declaration, processing instructions, comments and doctype.
+/// An HTML doctype is reported as an `html` root.
+fn root_element(body: &str) -> Option<&str> {
+    let mut rest = body;
+    loop {
+        rest = rest.trim_start();
+        if rest.starts_with("<?") {
+            rest = &rest[rest.find("?>")? + 2..];
+        } else if rest.starts_with("<!--") {
+            rest = &rest[rest.find("-->")? + 3..];
+        } else if rest.starts_with("<!") {
+            let end = rest.find('>')?;
+            if rest[..end].to_ascii_lowercase().starts_with("<!doctype html") {
+                return Some("html");
+            }
+            rest = &rest[end + 1..];
+        } else if let Some(tag) = rest.strip_prefix('<') {
+            let end = tag.find(|c: char| c.is_whitespace() || c == '>' || c == '/')?;
+            return Some(&tag[..end]);
+        } else {
+            return None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_rss() {
+        let body = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?xml-stylesheet type="text/xsl" href="/feed.xsl"?>
+<!-- generator: test -->
+<rss version="2.0"><channel></channel></rss>"#;
+        assert_eq!(sniff_format(Some("text/xml"), body), FeedFormat::Rss);
+    }
+
+    #[test]
+    fn test_sniff_atom_and_rdf() {
+        assert_eq!(sniff_format(None, r#"<feed xmlns="http://www.w3.org/2005/Atom">"#), FeedFormat::Atom);
+        assert_eq!(sniff_format(None, r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">"#), FeedFormat::Rdf);
+    }
+
+    #[test]
+    fn test_sniff_json_feed() {
+        let body = r#"{"version": "https://jsonfeed.org/version/1", "title": "t", "items": []}"#;
+        assert_eq!(sniff_format(Some("application/json"), body), FeedFormat::JsonFeed);
+        assert_eq!(sniff_format(Some("application/json"), r#"{"error": "not found"}"#), FeedFormat::Unknown);
+    }
+
+    #[test]
+    fn test_sniff_html() {
+        assert_eq!(sniff_format(Some("text/html"), "<!DOCTYPE html>\n<html><body>404</body></html>"), FeedFormat::Html);
+        assert_eq!(sniff_format(Some("text/html; charset=utf-8"), "Not found"), FeedFormat::Html);
+    }
+}
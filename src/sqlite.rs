@@ -0,0 +1,680 @@
+//! SQLite storage for laptops, small deployments and tests (`DATABASE_URL=sqlite:feeds.db` or `sqlite::memory:`)
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::info;
+use sqlx::{Pool, QueryBuilder, Row, Sqlite, Transaction};
+use sqlx::migrate::MigrateDatabase;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+use crate::config::DatabaseConfig;
+use crate::models::{Enclosure, FetchAttempt, Feed, Label, NewsItem, SaveStatus, Source, SourceType};
+use crate::storage::{duplicate_guids, news_change, NewsChange, Storage};
+
+/// SQLite's default `SQLITE_MAX_VARIABLE_NUMBER` before 3.32; multi-row statements are chunked under it
+const MAX_BIND_PARAMS: usize = 999;
+
+#[derive(Debug, Clone)]
+pub(crate) struct SqliteStorage {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteStorage {
+    pub(crate) async fn connect(config: &DatabaseConfig) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::from_str(&config.url)?.foreign_keys(true);
+        let mut pool_options = SqlitePoolOptions::new()
+            .max_connections(config.max_connections)
+            .acquire_timeout(config.acquire_timeout)
+            .idle_timeout(config.idle_timeout);
+        if config.url.contains(":memory:") {
+            // every connection gets its own in-memory database, so keep exactly one open
+            pool_options = pool_options.max_connections(1).min_connections(1).idle_timeout(None).max_lifetime(None);
+        }
+        let pool = pool_options.connect_with(options).await?;
+        Ok(Self { pool })
+    }
+
+    /// Create the database file if needed, connect, then apply the migrations embedded from `migrations/sqlite/`
+    pub(crate) async fn migrate(config: &DatabaseConfig) -> anyhow::Result<Self> {
+        if !config.url.contains(":memory:") && !Sqlite::database_exists(&config.url).await? {
+            info!("Creating database");
+            Sqlite::create_database(&config.url).await?;
+        }
+        let db = Self::connect(config).await?;
+        sqlx::migrate!("./migrations/sqlite").run(&db.pool).await?;
+        Ok(db)
+    }
+
+    async fn upsert_feed(tx: &mut Transaction<'_, Sqlite>, feed: &Feed) -> anyhow::Result<uuid::Uuid> {
+        let id = sqlx::query_scalar(r#"
+INSERT INTO feed (id, url, title, source_id, feed_type, ttl, etag, last_modified, create_timestamp)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT (url) DO UPDATE SET title = COALESCE(excluded.title, feed.title), feed_type = excluded.feed_type,
+    ttl = excluded.ttl, etag = excluded.etag, last_modified = excluded.last_modified
+RETURNING id
+        "#)
+            .bind(feed.id)
+            .bind(&feed.url)
+            .bind(&feed.title)
+            .bind(feed.source_id)
+            .bind(&feed.feed_type)
+            .bind(feed.ttl)
+            .bind(&feed.etag)
+            .bind(&feed.last_modified)
+            .bind(feed.create_timestamp)
+            .fetch_one(&mut *tx)
+            .await?;
+        Ok(id)
+    }
+
+    /// Upsert a feed's items with multi-row statements, in chunks that stay under the bind parameter limit
+    async fn save_items(tx: &mut Transaction<'_, Sqlite>, feed_id: uuid::Uuid, items: &[(NewsItem, Vec<String>)]) -> anyhow::Result<Vec<(uuid::Uuid, SaveStatus)>> {
+        let mut existing = HashMap::new();
+        for chunk in items.chunks(MAX_BIND_PARAMS - 1) {
+            let mut query = QueryBuilder::<Sqlite>::new("SELECT id, guid, title, url, content_hash FROM news WHERE feed_id = ");
+            query.push_bind(feed_id).push(" AND guid IN (");
+            let mut guids = query.separated(", ");
+            for (ni, _) in chunk {
+                guids.push_bind(&ni.guid);
+            }
+            query.push(")");
+            for row in query.build().fetch_all(&mut *tx).await? {
+                let guid: String = row.try_get("guid")?;
+                let stored: (uuid::Uuid, String, String, Option<String>) =
+                    (row.try_get("id")?, row.try_get("title")?, row.try_get("url")?, row.try_get("content_hash")?);
+                existing.insert(guid, stored);
+            }
+        }
+
+        let mut saved: Vec<(uuid::Uuid, SaveStatus)> = Vec::with_capacity(items.len());
+        let mut inserts = Vec::new();
+        let mut backfills = Vec::new();
+        let mut revisions = Vec::new();
+        for ((ni, _), duplicate_of) in items.iter().zip(duplicate_guids(items)) {
+            if let Some(j) = duplicate_of {
+                saved.push((saved[j].0, SaveStatus::Unchanged));
+                continue;
+            }
+            let Some((id, title, url, content_hash)) = existing.get(&ni.guid) else {
+                inserts.push(ni);
+                saved.push((ni.id, SaveStatus::Inserted));
+                continue;
+            };
+            match news_change(title, url, content_hash.as_deref(), ni) {
+                NewsChange::Unchanged => saved.push((*id, SaveStatus::Unchanged)),
+                NewsChange::BackfillHash => {
+                    backfills.push((*id, ni));
+                    saved.push((*id, SaveStatus::Unchanged));
+                }
+                NewsChange::Revise => {
+                    revisions.push((*id, ni));
+                    saved.push((*id, SaveStatus::Updated));
+                }
+            }
+        }
+
+        for chunk in inserts.chunks(MAX_BIND_PARAMS / 12) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "INSERT INTO news (id, title, url, published_timestamp, guid, feed_id, create_timestamp, authors, summary, content, content_hash, updated_timestamp) ");
+            query.push_values(chunk, |mut row, ni| {
+                row.push_bind(ni.id)
+                    .push_bind(&ni.title)
+                    .push_bind(&ni.url)
+                    .push_bind(ni.published_timestamp)
+                    .push_bind(&ni.guid)
+                    .push_bind(feed_id)
+                    .push_bind(ni.create_timestamp)
+                    .push_bind(&ni.authors)
+                    .push_bind(&ni.summary)
+                    .push_bind(&ni.content)
+                    .push_bind(&ni.content_hash)
+                    .push_bind(ni.updated_timestamp);
+            });
+            query.build().execute(&mut *tx).await?;
+        }
+
+        for chunk in backfills.chunks(MAX_BIND_PARAMS / 4) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                "UPDATE news SET summary = t.column2, content = t.column3, content_hash = t.column4 FROM (");
+            query.push_values(chunk, |mut row, (id, ni)| {
+                row.push_bind(*id).push_bind(&ni.summary).push_bind(&ni.content).push_bind(&ni.content_hash);
+            });
+            query.push(") AS t WHERE news.id = t.column1");
+            query.build().execute(&mut *tx).await?;
+        }
+
+        let now = Utc::now();
+        for chunk in revisions.chunks(MAX_BIND_PARAMS / 8) {
+            let mut query = QueryBuilder::<Sqlite>::new(r#"
+INSERT INTO news_revision (news_id, title, url, summary, content, content_hash, valid_from, revision_timestamp)
+SELECT id, title, url, summary, content, content_hash, COALESCE(updated_timestamp, create_timestamp), "#);
+            query.push_bind(now).push(" FROM news WHERE id IN (");
+            let mut ids = query.separated(", ");
+            for (id, _) in chunk {
+                ids.push_bind(*id);
+            }
+            query.push(")");
+            query.build().execute(&mut *tx).await?;
+
+            let mut query = QueryBuilder::<Sqlite>::new(r#"
+UPDATE news
+SET title = t.column2, url = t.column3, authors = t.column4, summary = t.column5, content = t.column6,
+    content_hash = t.column7, updated_timestamp = t.column8
+FROM ("#);
+            query.push_values(chunk, |mut row, (id, ni)| {
+                row.push_bind(*id)
+                    .push_bind(&ni.title)
+                    .push_bind(&ni.url)
+                    .push_bind(&ni.authors)
+                    .push_bind(&ni.summary)
+                    .push_bind(&ni.content)
+                    .push_bind(&ni.content_hash)
+                    .push_bind(ni.updated_timestamp.unwrap_or(now));
+            });
+            query.push(") AS t WHERE news.id = t.column1");
+            query.build().execute(&mut *tx).await?;
+        }
+
+        // one (news id, category name) pair per category of each item
+        let links: Vec<(uuid::Uuid, &String)> = items.iter().zip(&saved)
+            .flat_map(|((_, categories), (id, _))| categories.iter().map(move |c| (*id, c)))
+            .collect();
+        let mut names: Vec<&String> = links.iter().map(|(_, name)| *name).collect();
+        names.sort();
+        names.dedup();
+        for chunk in names.chunks(MAX_BIND_PARAMS) {
+            let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO category (name) ");
+            query.push_values(chunk, |mut row, name| {
+                row.push_bind(*name);
+            });
+            query.push(" ON CONFLICT (name) DO NOTHING");
+            query.build().execute(&mut *tx).await?;
+        }
+        for chunk in links.chunks(MAX_BIND_PARAMS / 2) {
+            // the WHERE tells SQLite's parser the ON CONFLICT isn't a join constraint
+            let mut query = QueryBuilder::<Sqlite>::new("INSERT INTO news_category (news_id, category_id) SELECT t.column1, c.id FROM (");
+            query.push_values(chunk, |mut row, (id, name)| {
+                row.push_bind(*id).push_bind(*name);
+            });
+            query.push(") AS t JOIN category c ON c.name = t.column2 WHERE true ON CONFLICT DO NOTHING");
+            query.build().execute(&mut *tx).await?;
+        }
+
+        Ok(saved)
+    }
+
+    async fn save_label(tx: &mut Transaction<'_, Sqlite>, label: &str) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO label (name) VALUES (?) ON CONFLICT (name) DO NOTHING")
+            .bind(label)
+            .execute(&mut *tx)
+            .await?;
+        Ok(())
+    }
+
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn source_types(&self) -> anyhow::Result<Vec<SourceType>> {
+        Ok(sqlx::query_as("SELECT * FROM source_type")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn source_type_by_name(&self, name: &str) -> anyhow::Result<Option<SourceType>> {
+        Ok(sqlx::query_as("SELECT * FROM source_type WHERE name = ?")
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn save_source_type(&self, source_type: &SourceType) -> anyhow::Result<i32> {
+        sqlx::query("INSERT INTO source_type (id, name) VALUES (?, ?)")
+            .bind(source_type.id)
+            .bind(&source_type.name)
+            .execute(&self.pool)
+            .await?;
+        Ok(source_type.id)
+    }
+
+    async fn sources(&self) -> anyhow::Result<Vec<Source>> {
+        Ok(sqlx::query_as("SELECT * FROM source")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn source_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Source>> {
+        Ok(sqlx::query_as("SELECT * FROM source WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn source_by_url(&self, url: &str) -> anyhow::Result<Option<Source>> {
+        Ok(sqlx::query_as("SELECT * FROM source WHERE url = ?")
+            .bind(url)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn save_source(&self, source: &Source) -> anyhow::Result<uuid::Uuid> {
+        sqlx::query(r#"
+INSERT INTO source (id, name, url, type_id, paywall, feed_available, description, short_name, state, city, create_timestamp, skip_tls_verify,
+                    requests_per_second, max_in_flight)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT (url) DO NOTHING
+        "#)
+            .bind(source.id)
+            .bind(&source.name)
+            .bind(&source.url)
+            .bind(source.type_id)
+            .bind(source.paywall)
+            .bind(source.feed_available)
+            .bind(&source.description)
+            .bind(&source.short_name)
+            .bind(&source.state)
+            .bind(&source.city)
+            .bind(source.create_timestamp)
+            .bind(source.skip_tls_verify)
+            .bind(source.requests_per_second)
+            .bind(source.max_in_flight)
+            .execute(&self.pool)
+            .await?;
+        let id = sqlx::query_scalar("SELECT id FROM source WHERE url = ?")
+            .bind(&source.url)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(id)
+    }
+
+    async fn feeds(&self) -> anyhow::Result<Vec<Feed>> {
+        Ok(sqlx::query_as("SELECT * FROM feed")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn feed_by_url(&self, url: &str) -> anyhow::Result<Option<Feed>> {
+        Ok(sqlx::query_as(r#"
+SELECT * FROM feed
+WHERE url = ?1 OR id IN (SELECT feed_id FROM feed_url_alias WHERE url = ?1)
+ORDER BY url = ?1 DESC
+LIMIT 1
+        "#)
+            .bind(url)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn feed_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Feed>> {
+        Ok(sqlx::query_as("SELECT * FROM feed WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?)
+    }
+
+    async fn save_feed(&self, feed: &Feed) -> anyhow::Result<uuid::Uuid> {
+        let mut tx = self.pool.begin().await?;
+        let id = Self::upsert_feed(&mut tx, feed).await?;
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn move_feed(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(r#"
+INSERT INTO feed_url_alias (url, feed_id)
+SELECT url, id FROM feed WHERE id = ?
+ON CONFLICT (url) DO UPDATE SET feed_id = excluded.feed_id
+        "#)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        sqlx::query("UPDATE feed SET url = ? WHERE id = ?")
+            .bind(url)
+            .bind(id)
+            .execute(&mut tx)
+            .await?;
+        // moving back to a former url
+        sqlx::query("DELETE FROM feed_url_alias WHERE url = ?")
+            .bind(url)
+            .execute(&mut tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn save_feed_alias(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()> {
+        sqlx::query("INSERT INTO feed_url_alias (url, feed_id) VALUES (?, ?) ON CONFLICT (url) DO UPDATE SET feed_id = excluded.feed_id")
+            .bind(url)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_feed_health(&self, feed: &Feed) -> anyhow::Result<()> {
+        sqlx::query(r#"
+UPDATE feed
+SET status = ?, status_reason = ?, status_timestamp = ?, consecutive_failures = ?,
+    last_success_timestamp = ?, last_new_item_timestamp = ?, next_poll_timestamp = ?
+WHERE id = ?
+        "#)
+            .bind(&feed.status)
+            .bind(&feed.status_reason)
+            .bind(feed.status_timestamp)
+            .bind(feed.consecutive_failures)
+            .bind(feed.last_success_timestamp)
+            .bind(feed.last_new_item_timestamp)
+            .bind(feed.next_poll_timestamp)
+            .bind(feed.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn labels(&self) -> anyhow::Result<Vec<Label>> {
+        Ok(sqlx::query_as("SELECT * FROM label ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn save_source_labels(&self, source_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for label in labels {
+            Self::save_label(&mut tx, label).await?;
+            sqlx::query("INSERT INTO source_label (source_id, label_id) SELECT ?, id FROM label WHERE name = ? ON CONFLICT DO NOTHING")
+                .bind(source_id)
+                .bind(label)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn save_feed_labels(&self, feed_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+        for label in labels {
+            Self::save_label(&mut tx, label).await?;
+            sqlx::query("INSERT INTO feed_label (feed_id, label_id) SELECT ?, id FROM label WHERE name = ? ON CONFLICT DO NOTHING")
+                .bind(feed_id)
+                .bind(label)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn sources_by_label(&self, name: &str) -> anyhow::Result<Vec<Source>> {
+        Ok(sqlx::query_as(r#"
+SELECT s.* FROM source s
+JOIN source_label sl ON sl.source_id = s.id
+JOIN label l ON l.id = sl.label_id
+WHERE l.name = ?
+ORDER BY s.url
+        "#)
+            .bind(name)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn feeds_by_label(&self, name: &str) -> anyhow::Result<Vec<Feed>> {
+        Ok(sqlx::query_as(r#"
+SELECT f.* FROM feed f
+JOIN feed_label fl ON fl.feed_id = f.id
+JOIN label l ON l.id = fl.label_id
+WHERE l.name = ?
+ORDER BY f.url
+        "#)
+            .bind(name)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn news(&self) -> anyhow::Result<Vec<NewsItem>> {
+        Ok(sqlx::query_as("SELECT * FROM news")
+            .fetch_all(&self.pool)
+            .await?)
+    }
+
+    async fn save_news_item(&self, ni: &NewsItem) -> anyhow::Result<(uuid::Uuid, SaveStatus)> {
+        let mut tx = self.pool.begin().await?;
+        let saved = Self::save_items(&mut tx, ni.feed_id, &[(ni.clone(), Vec::new())]).await?;
+        tx.commit().await?;
+        Ok(saved[0])
+    }
+
+    async fn save_feed_items(&self, feed: &Feed, items: &[(NewsItem, Vec<String>)]) -> anyhow::Result<(uuid::Uuid, Vec<(uuid::Uuid, SaveStatus)>)> {
+        let mut tx = self.pool.begin().await?;
+        let feed_id = Self::upsert_feed(&mut tx, feed).await?;
+        let saved = Self::save_items(&mut tx, feed_id, items).await?;
+        tx.commit().await?;
+        Ok((feed_id, saved))
+    }
+
+    async fn save_enclosure(&self, enclosure: &Enclosure) -> anyhow::Result<(uuid::Uuid, Option<String>)> {
+        let rec = sqlx::query(r#"
+INSERT INTO enclosure (id, news_id, url, mime_type, length, duration, kind, create_timestamp)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+ON CONFLICT (news_id, url) DO UPDATE SET mime_type = excluded.mime_type, length = excluded.length, duration = excluded.duration
+RETURNING id, content_path
+        "#)
+            .bind(enclosure.id)
+            .bind(enclosure.news_id)
+            .bind(&enclosure.url)
+            .bind(&enclosure.mime_type)
+            .bind(enclosure.length)
+            .bind(enclosure.duration)
+            .bind(&enclosure.kind)
+            .bind(enclosure.create_timestamp)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok((rec.try_get("id")?, rec.try_get("content_path")?))
+    }
+
+    async fn set_enclosure_content_path(&self, id: uuid::Uuid, content_path: &str) -> anyhow::Result<()> {
+        sqlx::query("UPDATE enclosure SET content_path = ? WHERE id = ?")
+            .bind(content_path)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn save_fetch_attempt(&self, attempt: &FetchAttempt) -> anyhow::Result<()> {
+        sqlx::query(r#"
+INSERT INTO fetch_attempt (id, feed_id, url, started_timestamp, finished_timestamp, http_status, bytes, content_hash, format, new_items, error_class)
+VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#)
+            .bind(attempt.id)
+            .bind(attempt.feed_id)
+            .bind(&attempt.url)
+            .bind(attempt.started_timestamp)
+            .bind(attempt.finished_timestamp)
+            .bind(attempt.http_status)
+            .bind(attempt.bytes)
+            .bind(&attempt.content_hash)
+            .bind(&attempt.format)
+            .bind(attempt.new_items)
+            .bind(&attempt.error_class)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn fetch_attempts_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<FetchAttempt>> {
+        Ok(sqlx::query_as("SELECT * FROM fetch_attempt WHERE started_timestamp >= ? ORDER BY started_timestamp")
+            .bind(since)
+            .fetch_all(&self.pool)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::models::{FEED_DEAD, SaveCounts, WEBSITE_SOURCE_TYPE_ID};
+    use crate::storage::feed_fixture;
+
+    async fn memory_storage() -> SqliteStorage {
+        let config = DatabaseConfig {
+            url: "sqlite::memory:".to_string(),
+            max_connections: 1,
+            acquire_timeout: Duration::from_secs(5),
+            idle_timeout: Duration::from_secs(600),
+        };
+        SqliteStorage::migrate(&config).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_save_source_and_feed() {
+        let db = memory_storage().await;
+        let (source_id, feed_id) = feed_fixture(&db).await;
+        let again = Source::new("Example again".to_string(), "https://example.org".to_string(), WEBSITE_SOURCE_TYPE_ID);
+        assert_eq!(db.save_source(&again).await.unwrap(), source_id);
+
+        let mut feed = Feed::new(source_id, "https://example.org/feed".to_string(), None, None);
+        feed.etag = Some("\"v2\"".to_string());
+        assert_eq!(db.save_feed(&feed).await.unwrap(), feed_id);
+        assert_eq!(db.feed_by_url("https://example.org/feed").await.unwrap().unwrap().etag.as_deref(), Some("\"v2\""));
+    }
+
+    #[tokio::test]
+    async fn test_save_feed_updates_type_and_title() {
+        let db = memory_storage().await;
+        let (source_id, feed_id) = feed_fixture(&db).await;
+        let save = |title: Option<&str>, feed_type: &str| {
+            Feed::new(source_id, "https://example.org/feed".to_string(), title.map(|t| t.to_string()), Some(feed_type.to_string()))
+        };
+
+        db.save_feed(&save(Some("Old"), "RSS")).await.unwrap();
+        db.save_feed(&save(None, "Atom")).await.unwrap();
+        let stored = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        assert_eq!((stored.feed_type.as_deref(), stored.title.as_deref()), (Some("Atom"), Some("Old")));
+
+        db.save_feed(&save(Some("New"), "JSON Feed")).await.unwrap();
+        let stored = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        assert_eq!((stored.feed_type.as_deref(), stored.title.as_deref()), (Some("JSON Feed"), Some("New")));
+    }
+
+    #[tokio::test]
+    async fn test_save_news_item() {
+        let db = memory_storage().await;
+        let (_, feed_id) = feed_fixture(&db).await;
+
+        let mut item = NewsItem::new(feed_id, "1".to_string(), "Title".to_string(), Utc::now(), "https://example.org/1".to_string());
+        item.set_content(Some("summary".to_string()), None);
+        let (id, status) = db.save_news_item(&item).await.unwrap();
+        assert_eq!(status, SaveStatus::Inserted);
+
+        item.id = uuid::Uuid::new_v4();
+        assert_eq!(db.save_news_item(&item).await.unwrap(), (id, SaveStatus::Unchanged));
+
+        item.title = "New title".to_string();
+        assert_eq!(db.save_news_item(&item).await.unwrap(), (id, SaveStatus::Updated));
+        let revisions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM news_revision").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(revisions, 1);
+
+        assert_eq!(db.news().await.unwrap()[0].title, "New title");
+    }
+
+    #[tokio::test]
+    async fn test_save_feed_items() {
+        let db = memory_storage().await;
+        let (_, feed_id) = feed_fixture(&db).await;
+        let feed = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        let item = |guid: &str, title: &str| NewsItem::new(feed_id, guid.to_string(), title.to_string(), Utc::now(), format!("https://example.org/{}", guid));
+        let categories = vec!["rust".to_string(), "rust".to_string()];
+
+        let items = vec![(item("1", "One"), categories.clone()), (item("2", "Two"), vec![]), (item("1", "One again"), vec![])];
+        let (saved_to, saved) = db.save_feed_items(&feed, &items).await.unwrap();
+        assert_eq!(saved_to, feed_id);
+        assert_eq!(SaveCounts::from_statuses(saved.iter().map(|(_, s)| s)), SaveCounts { inserted: 2, updated: 0, unchanged: 1 });
+        assert_eq!(saved[2].0, saved[0].0);
+
+        let items = vec![(item("1", "One"), categories), (item("2", "Two, edited"), vec![]), (item("3", "Three"), vec![])];
+        let (_, saved) = db.save_feed_items(&feed, &items).await.unwrap();
+        assert_eq!(SaveCounts::from_statuses(saved.iter().map(|(_, s)| s)), SaveCounts { inserted: 1, updated: 1, unchanged: 1 });
+        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM news_category").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(links, 1);
+    }
+
+    #[tokio::test]
+    async fn test_save_feed_items_in_chunks() {
+        let db = memory_storage().await;
+        let (_, feed_id) = feed_fixture(&db).await;
+        let feed = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        // more rows than fit in one statement of each kind
+        let items = |title: &str| -> Vec<(NewsItem, Vec<String>)> {
+            (0..1200)
+                .map(|i| (NewsItem::new(feed_id, i.to_string(), format!("{} {}", title, i), Utc::now(), format!("https://example.org/{}", i)), vec![format!("c{}", i % 3)]))
+                .collect()
+        };
+
+        let (_, saved) = db.save_feed_items(&feed, &items("Item")).await.unwrap();
+        assert_eq!(SaveCounts::from_statuses(saved.iter().map(|(_, s)| s)).inserted, 1200);
+        let (_, saved) = db.save_feed_items(&feed, &items("Edited")).await.unwrap();
+        assert_eq!(SaveCounts::from_statuses(saved.iter().map(|(_, s)| s)).updated, 1200);
+        let revisions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM news_revision").fetch_one(&db.pool).await.unwrap();
+        let links: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM news_category").fetch_one(&db.pool).await.unwrap();
+        assert_eq!((revisions, links), (1200, 1200));
+    }
+
+    #[tokio::test]
+    async fn test_save_feed_health() {
+        let db = memory_storage().await;
+        let (_, feed_id) = feed_fixture(&db).await;
+        let mut feed = db.feed_by_id(feed_id).await.unwrap().unwrap();
+
+        feed.status = FEED_DEAD.to_string();
+        feed.status_reason = Some("10 consecutive failures".to_string());
+        feed.consecutive_failures = 10;
+        feed.next_poll_timestamp = Some(Utc::now());
+        db.save_feed_health(&feed).await.unwrap();
+        // refreshing the validators leaves the health alone
+        db.save_feed(&feed).await.unwrap();
+
+        let stored = db.feed_by_id(feed_id).await.unwrap().unwrap();
+        assert_eq!((stored.status.as_str(), stored.consecutive_failures), (FEED_DEAD, 10));
+        assert_eq!(stored.status_reason, feed.status_reason);
+        assert!(stored.next_poll_timestamp.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_move_feed() {
+        let db = memory_storage().await;
+        let (_, feed_id) = feed_fixture(&db).await;
+
+        db.move_feed(feed_id, "http://example.org/feed").await.unwrap();
+        assert_eq!(db.feed_by_url("http://example.org/feed").await.unwrap().unwrap().id, feed_id);
+        assert_eq!(db.feed_by_url("https://example.org/feed").await.unwrap().unwrap().url, "http://example.org/feed");
+
+        // and back again
+        db.move_feed(feed_id, "https://example.org/feed").await.unwrap();
+        assert_eq!(db.feed_by_url("http://example.org/feed").await.unwrap().unwrap().url, "https://example.org/feed");
+        let aliases: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM feed_url_alias").fetch_one(&db.pool).await.unwrap();
+        assert_eq!(aliases, 1);
+    }
+
+    #[tokio::test]
+    async fn test_labels() {
+        let db = memory_storage().await;
+        let (source_id, feed_id) = feed_fixture(&db).await;
+        let labels = vec!["Tech/Rust".to_string(), "Must Read".to_string()];
+        db.save_source_labels(source_id, &labels).await.unwrap();
+        db.save_feed_labels(feed_id, &labels).await.unwrap();
+        db.save_feed_labels(feed_id, &labels[1..]).await.unwrap();
+
+        let names: Vec<String> = db.labels().await.unwrap().into_iter().map(|l| l.name).collect();
+        assert_eq!(names, vec!["Must Read", "Tech/Rust"]);
+        assert_eq!(db.sources_by_label("Tech/Rust").await.unwrap()[0].id, source_id);
+        assert_eq!(db.feeds_by_label("Must Read").await.unwrap().len(), 1);
+        assert!(db.feeds_by_label("Tech").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+//! Persistence behind a `Storage` trait. `DATABASE_URL` picks the backend at runtime:
+//! `postgres://` (feature `postgres`) or `sqlite:` (feature `sqlite`).
+
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::config::DatabaseConfig;
+#[cfg(feature = "postgres")]
+use crate::db::PgStorage;
+use crate::models::{Enclosure, FetchAttempt, Feed, Label, NewsItem, SaveStatus, Source, SourceType};
+#[cfg(feature = "sqlite")]
+use crate::sqlite::SqliteStorage;
+
+#[async_trait]
+pub(crate) trait Storage: Send + Sync {
+    async fn source_types(&self) -> anyhow::Result<Vec<SourceType>>;
+    #[allow(dead_code)]
+    async fn source_type_by_name(&self, name: &str) -> anyhow::Result<Option<SourceType>>;
+    #[allow(dead_code)]
+    async fn save_source_type(&self, source_type: &SourceType) -> anyhow::Result<i32>;
+
+    async fn sources(&self) -> anyhow::Result<Vec<Source>>;
+    async fn source_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Source>>;
+    async fn source_by_url(&self, url: &str) -> anyhow::Result<Option<Source>>;
+    /// Insert a source unless one with the same url exists; returns the stored id
+    async fn save_source(&self, source: &Source) -> anyhow::Result<uuid::Uuid>;
+
+    async fn feeds(&self) -> anyhow::Result<Vec<Feed>>;
+    /// The feed stored under url, or under the url it moved to from url
+    async fn feed_by_url(&self, url: &str) -> anyhow::Result<Option<Feed>>;
+    async fn feed_by_id(&self, id: uuid::Uuid) -> anyhow::Result<Option<Feed>>;
+    /// Insert a feed, or refresh the ttl and HTTP cache validators (`ETag` / `Last-Modified`) of an existing one
+    async fn save_feed(&self, feed: &Feed) -> anyhow::Result<uuid::Uuid>;
+    /// Point a feed at the url it permanently moved to, keeping its current url as an alias
+    async fn move_feed(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()>;
+    /// Record url as a former url of a feed
+    async fn save_feed_alias(&self, id: uuid::Uuid, url: &str) -> anyhow::Result<()>;
+    /// Update a feed's status, failure count and poll timestamps
+    async fn save_feed_health(&self, feed: &Feed) -> anyhow::Result<()>;
+
+    /// All labels, by name
+    async fn labels(&self) -> anyhow::Result<Vec<Label>>;
+    /// Attach labels to a source, creating the ones that don't exist yet
+    async fn save_source_labels(&self, source_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()>;
+    /// Attach labels to a feed, creating the ones that don't exist yet
+    async fn save_feed_labels(&self, feed_id: uuid::Uuid, labels: &[String]) -> anyhow::Result<()>;
+    async fn sources_by_label(&self, name: &str) -> anyhow::Result<Vec<Source>>;
+    async fn feeds_by_label(&self, name: &str) -> anyhow::Result<Vec<Feed>>;
+
+    #[allow(dead_code)]
+    async fn news(&self) -> anyhow::Result<Vec<NewsItem>>;
+    /// Insert a news item, or update it when the publisher changed its title, url or content.
+    /// The values being replaced are kept in `news_revision`.
+    #[allow(dead_code)]
+    async fn save_news_item(&self, news_item: &NewsItem) -> anyhow::Result<(uuid::Uuid, SaveStatus)>;
+    /// Save a feed and all its items, each with its categories, in one transaction. Items repeating
+    /// the guid of an earlier item are skipped. Returns the feed id and each item's id and status, in order.
+    async fn save_feed_items(&self, feed: &Feed, items: &[(NewsItem, Vec<String>)]) -> anyhow::Result<(uuid::Uuid, Vec<(uuid::Uuid, SaveStatus)>)>;
+
+    /// Insert or refresh an enclosure; returns its id and where it was downloaded to, if it was
+    async fn save_enclosure(&self, enclosure: &Enclosure) -> anyhow::Result<(uuid::Uuid, Option<String>)>;
+    async fn set_enclosure_content_path(&self, id: uuid::Uuid, content_path: &str) -> anyhow::Result<()>;
+
+    async fn save_fetch_attempt(&self, attempt: &FetchAttempt) -> anyhow::Result<()>;
+    /// Fetch attempts started at or after `since`, oldest first
+    async fn fetch_attempts_since(&self, since: DateTime<Utc>) -> anyhow::Result<Vec<FetchAttempt>>;
+}
+
+/// Connect to the storage backend named by the `DATABASE_URL` scheme
+pub(crate) async fn connect(config: &DatabaseConfig) -> anyhow::Result<Box<dyn Storage>> {
+    match scheme(&config.url) {
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => Ok(Box::new(PgStorage::connect(config).await?)),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Ok(Box::new(SqliteStorage::connect(config).await?)),
+        other => anyhow::bail!("Unsupported DATABASE_URL scheme '{}' for this build", other),
+    }
+}
+
+/// Create the database if needed and apply the backend's embedded migrations
+pub(crate) async fn migrate(config: &DatabaseConfig) -> anyhow::Result<()> {
+    match scheme(&config.url) {
+        #[cfg(feature = "postgres")]
+        "postgres" | "postgresql" => PgStorage::migrate(config).await.map(|_| ()),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => SqliteStorage::migrate(config).await.map(|_| ()),
+        other => anyhow::bail!("Unsupported DATABASE_URL scheme '{}' for this build", other),
+    }
+}
+
+fn scheme(url: &str) -> &str {
+    url.split(':').next().unwrap_or_default()
+}
+
+/// For each item, the index of an earlier item in the batch with the same guid
+pub(crate) fn duplicate_guids(items: &[(NewsItem, Vec<String>)]) -> Vec<Option<usize>> {
+    let mut first: HashMap<&str, usize> = HashMap::new();
+    items.iter().enumerate()
+        .map(|(i, (item, _))| {
+            let j = *first.entry(item.guid.as_str()).or_insert(i);
+            (j != i).then_some(j)
+        })
+        .collect()
+}
+
+/// What saving `news_item` should do to the stored row with the same feed and guid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NewsChange {
+    Unchanged,
+    /// Rows saved before content hashing was added have no hash to compare against; backfill it quietly
+    BackfillHash,
+    /// The title, url or content changed: keep the stored values as a revision, then update
+    Revise,
+}
+
+pub(crate) fn news_change(stored_title: &str, stored_url: &str, stored_hash: Option<&str>, news_item: &NewsItem) -> NewsChange {
+    let content_changed = stored_hash.is_some() && stored_hash != news_item.content_hash.as_deref();
+    if stored_title != news_item.title || stored_url != news_item.url || content_changed {
+        NewsChange::Revise
+    } else if stored_hash.is_none() && news_item.content_hash.is_some() {
+        NewsChange::BackfillHash
+    } else {
+        NewsChange::Unchanged
+    }
+}
+
+/// The source and feed most storage tests start from: `https://example.org` and its `/feed`
+#[cfg(test)]
+pub(crate) async fn feed_fixture(db: &dyn Storage) -> (uuid::Uuid, uuid::Uuid) {
+    use crate::models::WEBSITE_SOURCE_TYPE_ID;
+
+    let source_id = db.save_source(&Source::new("Example".to_string(), "https://example.org".to_string(), WEBSITE_SOURCE_TYPE_ID)).await.unwrap();
+    let feed_id = db.save_feed(&Feed::new(source_id, "https://example.org/feed".to_string(), None, None)).await.unwrap();
+    (source_id, feed_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_news_change() {
+        let mut item = NewsItem::new(uuid::Uuid::new_v4(), "1".to_string(), "Title".to_string(), Utc::now(), "https://example.org/1".to_string());
+        item.set_content(Some("summary".to_string()), None);
+        let hash = item.content_hash.clone();
+
+        assert_eq!(news_change("Title", "https://example.org/1", hash.as_deref(), &item), NewsChange::Unchanged);
+        assert_eq!(news_change("Title", "https://example.org/1", None, &item), NewsChange::BackfillHash);
+        assert_eq!(news_change("Old title", "https://example.org/1", hash.as_deref(), &item), NewsChange::Revise);
+        assert_eq!(news_change("Title", "https://example.org/1", Some("old hash"), &item), NewsChange::Revise);
+    }
+
+    #[test]
+    fn test_scheme() {
+        assert_eq!(scheme("postgres://postgres@localhost/feeds"), "postgres");
+        assert_eq!(scheme("sqlite:feeds.db"), "sqlite");
+        assert_eq!(scheme("sqlite::memory:"), "sqlite");
+    }
+}